@@ -0,0 +1,532 @@
+use crate::art::{art_check_alen, art_check_layout, art_index, ArtNode, Prefix, ART_DENSE_STRIDE};
+use crate::error::ArtError;
+use crate::layout::ArtLayout;
+use ipnet::{Ipv4Net, Ipv6Net};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+// Routing table keeping its tables and routes in a few contiguous vectors
+// instead of a graph of reference counted allocations.
+//
+// The entries of every table live in a single slot vector and each slot is
+// a 32bit handle on a route, a sub-table or nothing. Walking down the table
+// is a sequence of index computations into the same vectors, and dropping
+// the table frees a handful of allocations whatever the number of routes.
+// Freed tables and routes are kept on free lists and reused. As every table
+// takes all its slots at once, strides are limited to ART_DENSE_STRIDE.
+pub struct ArtArenaRoot<P, D> {
+    bits: Vec<u8>,
+    alen: u8,
+    // The root table is always tables[0].
+    tables: Vec<ArenaTable>,
+    // Freed tables of each level, reused together with their slots.
+    free_tables: Vec<Vec<usize>>,
+    slots: Vec<Slot>,
+    nodes: Vec<Option<ArtNode<P, D>>>,
+    free_nodes: Vec<usize>,
+}
+
+struct ArenaTable {
+    minfringe: u32,
+    level: u32,
+    bits: u8,
+    offset: u8,
+    // Number of routes and sub-tables held by this table.
+    refcnt: u32,
+    // Index of the first entry of the table in the slot vector.
+    base: usize,
+}
+
+// A table entry: 0 for no route, odd values for routes and even values for
+// sub-tables.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Slot(u32);
+
+impl Slot {
+    const NONE: Slot = Slot(0);
+
+    fn node(id: usize) -> Slot {
+        Slot(((id as u32) << 1) | 1)
+    }
+
+    fn table(id: usize) -> Slot {
+        Slot(((id as u32) + 1) << 1)
+    }
+
+    fn as_node(self) -> Option<usize> {
+        if self.0 & 1 == 1 {
+            Some((self.0 >> 1) as usize)
+        } else {
+            None
+        }
+    }
+
+    fn as_table(self) -> Option<usize> {
+        if self.0 != 0 && self.0 & 1 == 0 {
+            Some(((self.0 >> 1) - 1) as usize)
+        } else {
+            None
+        }
+    }
+}
+
+impl<P, D> ArtArenaRoot<P, D>
+where
+    P: Prefix + Copy,
+{
+    pub fn new(levels: u32, bits: Vec<u8>, alen: u8) -> Result<Self, ArtError> {
+        art_check_layout(levels, &bits, alen)?;
+        art_check_alen::<P>(alen, false)?;
+        if let Some(level) = bits.iter().position(|&stride| stride > ART_DENSE_STRIDE) {
+            return Err(ArtError::InvalidStride {
+                level,
+                bits: bits[level],
+            });
+        }
+
+        let minfringe = 1 << bits[0];
+        let mut ar = ArtArenaRoot {
+            free_tables: vec![Vec::new(); bits.len()],
+            bits,
+            alen,
+            tables: Vec::new(),
+            slots: Vec::new(),
+            nodes: Vec::new(),
+            free_nodes: Vec::new(),
+        };
+        ar.tables.push(ArenaTable {
+            minfringe,
+            level: 0,
+            bits: ar.bits[0],
+            offset: 0,
+            refcnt: 0,
+            base: 0,
+        });
+        ar.slots.resize((minfringe << 1) as usize, Slot::NONE);
+        Ok(ar)
+    }
+
+    pub fn with_layout(layout: &ArtLayout) -> Result<Self, ArtError> {
+        art_check_alen::<P>(layout.alen(), true)?;
+        ArtArenaRoot::new(layout.levels(), layout.bits().to_vec(), layout.alen())
+    }
+
+    fn slot(&self, t: usize, i: u32) -> Slot {
+        self.slots[self.tables[t].base + i as usize]
+    }
+
+    fn set_slot(&mut self, t: usize, i: u32, slot: Slot) {
+        let base = self.tables[t].base;
+        self.slots[base + i as usize] = slot;
+    }
+
+    // The route of an entry, looking through a sub-table to its default.
+    fn route_of(&self, slot: Slot) -> Slot {
+        match slot.as_table() {
+            Some(table) => self.slot(table, 1),
+            None => slot,
+        }
+    }
+
+    fn node(&self, slot: Slot) -> Option<&ArtNode<P, D>> {
+        slot.as_node().and_then(|id| self.nodes[id].as_ref())
+    }
+
+    fn bindex(&self, t: usize, addr: &[u8], plen: u8) -> Option<u32> {
+        let at = &self.tables[t];
+        art_index(at.offset, at.bits, addr, plen).map(|i| i as u32)
+    }
+
+    fn findex(&self, t: usize, addr: &[u8]) -> u32 {
+        let at = &self.tables[t];
+        art_index(at.offset, at.bits, addr, at.offset + at.bits).unwrap() as u32
+    }
+
+    fn covers(&self, t: usize, plen: u8) -> bool {
+        let at = &self.tables[t];
+        plen <= at.offset + at.bits
+    }
+
+    fn alloc_table(&mut self, parent: usize) -> usize {
+        let level = self.tables[parent].level + 1;
+        if let Some(t) = self.free_tables[level as usize].pop() {
+            return t;
+        }
+
+        let bits = self.bits[level as usize];
+        let minfringe = 1 << bits;
+        let base = self.slots.len();
+        self.slots
+            .resize(base + (minfringe << 1) as usize, Slot::NONE);
+        self.tables.push(ArenaTable {
+            minfringe,
+            level,
+            bits,
+            offset: self.tables[parent].offset + self.tables[parent].bits,
+            refcnt: 0,
+            base,
+        });
+        self.tables.len() - 1
+    }
+
+    // The slots are cleared so the table is ready for its next use.
+    fn free_table(&mut self, t: usize) {
+        let at = &self.tables[t];
+        let range = at.base..at.base + (at.minfringe << 1) as usize;
+        self.slots[range].fill(Slot::NONE);
+        self.free_tables[at.level as usize].push(t);
+    }
+
+    fn alloc_node(&mut self, an: ArtNode<P, D>) -> usize {
+        if let Some(id) = self.free_nodes.pop() {
+            self.nodes[id] = Some(an);
+            return id;
+        }
+        self.nodes.push(Some(an));
+        self.nodes.len() - 1
+    }
+
+    fn free_node(&mut self, id: usize) -> ArtNode<P, D> {
+        self.free_nodes.push(id);
+        self.nodes[id].take().unwrap()
+    }
+
+    // Insert the route and return the route it replaced for the same
+    // prefix, if any.
+    pub fn insert(&mut self, an: ArtNode<P, D>) -> Option<ArtNode<P, D>> {
+        let plen = an.prefix.prefix_len();
+        if plen > self.alen {
+            return None;
+        }
+
+        let addr = an.prefix.to_octets();
+        let mut t = 0;
+
+        while !self.covers(t, plen) {
+            let j = self.findex(t, addr.as_ref());
+            let entry = self.slot(t, j);
+
+            t = match entry.as_table() {
+                Some(table) => table,
+                None => {
+                    // Push the route of the fringe index, if any, down to
+                    // the new table as its default.
+                    let table = self.alloc_table(t);
+                    self.set_slot(table, 1, entry);
+                    self.set_slot(t, j, Slot::table(table));
+                    self.tables[t].refcnt += 1;
+                    table
+                }
+            };
+        }
+
+        let i = self.bindex(t, addr.as_ref(), plen).unwrap();
+        let prev = self.route_of(self.slot(t, i));
+
+        // Routes are referred to by handle, so replacing the route for the
+        // same prefix leaves the table untouched.
+        if let Some(id) = prev.as_node() {
+            if self.nodes[id].as_ref().unwrap().prefix.prefix_len() == plen {
+                return self.nodes[id].replace(an);
+            }
+        }
+
+        let new = Slot::node(self.alloc_node(an));
+        self.tables[t].refcnt += 1;
+
+        if i < self.tables[t].minfringe {
+            self.allot(t, i, prev, new);
+        } else if let Some(table) = self.slot(t, i).as_table() {
+            self.set_slot(table, 1, new);
+        } else {
+            self.set_slot(t, i, new);
+        }
+        None
+    }
+
+    fn allot(&mut self, t: usize, i: u32, old: Slot, new: Slot) {
+        let entry = self.slot(t, i);
+
+        if let Some(table) = entry.as_table() {
+            if self.slot(table, 1) == old {
+                self.set_slot(table, 1, new);
+            }
+            return;
+        }
+
+        // A more specific route owns this index and everything below it.
+        if entry != old {
+            return;
+        }
+        self.set_slot(t, i, new);
+
+        if i >= self.tables[t].minfringe {
+            return;
+        }
+        self.allot(t, i << 1, old, new);
+        self.allot(t, (i << 1) + 1, old, new);
+    }
+
+    pub fn lookup(&self, prefix: &P) -> Option<&ArtNode<P, D>> {
+        let plen = prefix.prefix_len();
+        let addr = prefix.to_octets();
+        let mut t = 0;
+        let mut default = self.slot(t, 1);
+
+        while !self.covers(t, plen) {
+            let entry = self.slot(t, self.findex(t, addr.as_ref()));
+
+            match entry.as_table() {
+                Some(table) => {
+                    t = table;
+                    if self.slot(t, 1) != Slot::NONE {
+                        default = self.slot(t, 1);
+                    }
+                }
+                None if entry == Slot::NONE => return self.node(default),
+                None => return self.node(entry),
+            }
+        }
+
+        let i = self.bindex(t, addr.as_ref(), plen).unwrap();
+        let entry = self.route_of(self.slot(t, i));
+        if entry != Slot::NONE {
+            return self.node(entry);
+        }
+        self.node(default)
+    }
+
+    // Longest match lookup of a full length address given as octets.
+    fn lookup_octets(&self, addr: &[u8]) -> Option<&ArtNode<P, D>> {
+        let mut t = 0;
+        let mut default = self.slot(t, 1);
+
+        loop {
+            let entry = self.slot(t, self.findex(t, addr));
+
+            match entry.as_table() {
+                Some(table) => {
+                    t = table;
+                    if self.slot(t, 1) != Slot::NONE {
+                        default = self.slot(t, 1);
+                    }
+                }
+                None if entry == Slot::NONE => return self.node(default),
+                None => return self.node(entry),
+            }
+        }
+    }
+
+    pub fn lookup_exact(&self, prefix: &P) -> Option<&ArtNode<P, D>> {
+        let plen = prefix.prefix_len();
+        if plen > self.alen {
+            return None;
+        }
+
+        let addr = prefix.to_octets();
+        let mut t = 0;
+
+        while !self.covers(t, plen) {
+            t = self.slot(t, self.findex(t, addr.as_ref())).as_table()?;
+        }
+
+        let i = self.bindex(t, addr.as_ref(), plen).unwrap();
+        self.node(self.route_of(self.slot(t, i)))
+            .filter(|node| node.prefix.prefix_len() == plen)
+    }
+
+    // Delete the route for the prefix and return it.
+    pub fn delete(&mut self, prefix: &P) -> Option<ArtNode<P, D>> {
+        let plen = prefix.prefix_len();
+        if plen > self.alen {
+            return None;
+        }
+
+        let addr = prefix.to_octets();
+        let mut path = Vec::new();
+        let mut t = 0;
+
+        while !self.covers(t, plen) {
+            let j = self.findex(t, addr.as_ref());
+            path.push((t, j));
+            t = self.slot(t, j).as_table()?;
+        }
+
+        let i = self.bindex(t, addr.as_ref(), plen).unwrap();
+        let prev = self.route_of(self.slot(t, i));
+
+        // Nothing to delete when the index only holds a less specific route
+        // alloted to it.
+        let id = prev.as_node()?;
+        if self.nodes[id].as_ref().unwrap().prefix.prefix_len() != plen {
+            return None;
+        }
+
+        let next = if (i >> 1) > 1 {
+            self.slot(t, i >> 1)
+        } else {
+            Slot::NONE
+        };
+
+        if i < self.tables[t].minfringe {
+            self.allot(t, i, prev, next);
+        } else if let Some(table) = self.slot(t, i).as_table() {
+            self.set_slot(table, 1, next);
+        } else {
+            self.set_slot(t, i, next);
+        }
+        self.tables[t].refcnt -= 1;
+
+        // Detach the tables left without any route or sub-table. The fringe
+        // index gets back the route that was pushed down as the default.
+        while let Some((parent, j)) = path.pop() {
+            if self.tables[t].refcnt > 0 {
+                break;
+            }
+            let default = self.slot(t, 1);
+            self.set_slot(parent, j, default);
+            self.tables[parent].refcnt -= 1;
+            self.free_table(t);
+            t = parent;
+        }
+
+        Some(self.free_node(id))
+    }
+
+    // Every route has its own slot in the node vector, freed or not.
+    pub fn len(&self) -> usize {
+        self.nodes.len() - self.free_nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Remove all routes at once, keeping only the root table.
+    pub fn clear(&mut self) {
+        let size = (self.tables[0].minfringe << 1) as usize;
+        self.tables.truncate(1);
+        self.tables[0].refcnt = 0;
+        self.free_tables.iter_mut().for_each(|free| free.clear());
+        self.slots.clear();
+        self.slots.resize(size, Slot::NONE);
+        self.nodes.clear();
+        self.free_nodes.clear();
+    }
+
+    pub fn iter(&self) -> ArtArenaIter<'_, P, D> {
+        ArtArenaIter {
+            root: self,
+            stack: Vec::new(),
+            at: 0,
+            i: 1,
+        }
+    }
+
+    pub fn route_ipv4_add(&mut self, prefix: P, data: D) -> Option<ArtNode<P, D>> {
+        self.insert(ArtNode {
+            prefix,
+            data: Some(data),
+        })
+    }
+
+    pub fn route_ipv4_delete(&mut self, prefix: P) -> Option<ArtNode<P, D>> {
+        self.delete(&prefix)
+    }
+
+    pub fn route_ipv4_lookup(&self, prefix: P) -> Option<&ArtNode<P, D>> {
+        self.lookup(&prefix)
+    }
+
+    pub fn route_ipv4_lookup_exact(&self, prefix: P) -> Option<&ArtNode<P, D>> {
+        self.lookup_exact(&prefix)
+    }
+
+    pub fn route_ipv6_add(&mut self, prefix: P, data: D) -> Option<ArtNode<P, D>> {
+        self.route_ipv4_add(prefix, data)
+    }
+
+    pub fn route_ipv6_delete(&mut self, prefix: P) -> Option<ArtNode<P, D>> {
+        self.route_ipv4_delete(prefix)
+    }
+
+    pub fn route_ipv6_lookup(&self, prefix: P) -> Option<&ArtNode<P, D>> {
+        self.route_ipv4_lookup(prefix)
+    }
+
+    pub fn route_ipv6_lookup_exact(&self, prefix: P) -> Option<&ArtNode<P, D>> {
+        self.route_ipv4_lookup_exact(prefix)
+    }
+}
+
+impl<D> ArtArenaRoot<Ipv4Net, D> {
+    pub fn new_ipv4_table() -> Self {
+        ArtArenaRoot::with_layout(&ArtLayout::ipv4_8_4_4_4_4_4_4()).unwrap()
+    }
+
+    pub fn lookup_addr(&self, addr: Ipv4Addr) -> Option<&ArtNode<Ipv4Net, D>> {
+        self.lookup_octets(&addr.octets())
+    }
+}
+
+impl<D> ArtArenaRoot<Ipv6Net, D> {
+    pub fn new_ipv6_table() -> Self {
+        ArtArenaRoot::with_layout(&ArtLayout::ipv6_4x32()).unwrap()
+    }
+
+    pub fn lookup_addr(&self, addr: Ipv6Addr) -> Option<&ArtNode<Ipv6Net, D>> {
+        self.lookup_octets(&addr.octets())
+    }
+}
+
+pub struct ArtArenaIter<'a, P, D> {
+    root: &'a ArtArenaRoot<P, D>,
+    stack: Vec<(usize, u32)>,
+    at: usize,
+    i: u32,
+}
+
+impl<'a, P, D> IntoIterator for &'a ArtArenaRoot<P, D>
+where
+    P: Prefix + Copy,
+{
+    type Item = &'a ArtNode<P, D>;
+    type IntoIter = ArtArenaIter<'a, P, D>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, P, D> Iterator for ArtArenaIter<'a, P, D>
+where
+    P: Prefix + Copy,
+{
+    type Item = &'a ArtNode<P, D>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let root = self.root;
+        loop {
+            while self.i < root.tables[self.at].minfringe << 1 {
+                let entry = root.slot(self.at, self.i);
+                if let Some(table) = entry.as_table() {
+                    self.stack.push((self.at, self.i + 1));
+                    self.at = table;
+                    self.i = 1;
+                    continue;
+                }
+
+                let i = self.i;
+                self.i += 1;
+                if let Some(node) = root.node(entry) {
+                    let addr = node.prefix.to_octets();
+                    if root.bindex(self.at, addr.as_ref(), node.prefix.prefix_len()) == Some(i) {
+                        return Some(node);
+                    }
+                }
+            }
+            let (parent, i) = self.stack.pop()?;
+            self.at = parent;
+            self.i = i;
+        }
+    }
+}
@@ -1,26 +1,58 @@
-use ipnet::Ipv4Net;
-use std::cell::RefCell;
-use std::rc::Rc;
-
+use crate::error::ArtError;
+use crate::layout::{ArtLayout, DefaultLayout};
+use crate::observer::{ArtChange, ArtDataMut, ArtObservers};
+use ipnet::{Ipv4Net, Ipv6Net};
+use std::cmp::Ordering;
+use std::collections::{btree_map, BTreeMap};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
+
+// Tables are shared through `Arc` and modified with `Arc::make_mut`, so a
+// table is copied only when someone else still holds it. `ArtRoot` is
+// `Send` and `Sync` whenever the prefix and the route data are.
 pub struct ArtRoot<P, D> {
     bits: Vec<u8>,
     levels: u32,
     alen: u8,
-    root: Option<Rc<ArtTable<P, D>>>,
+    root: Arc<ArtTable<P, D>>,
+    // Number of routes in the tables.
+    len: usize,
+    observers: ArtObservers<P, D>,
 }
 
 pub trait Prefix {
+    // Fixed size address octets, so the key can live on the stack.
+    type Octets: AsRef<[u8]>;
+    // Length of the addresses in bits.
+    const ALEN: u8;
+
     fn prefix_len(&self) -> u8;
-    fn to_octets(&self) -> Vec<u8>;
+    fn to_octets(&self) -> Self::Octets;
 }
 
 impl Prefix for Ipv4Net {
+    type Octets = [u8; 4];
+    const ALEN: u8 = 32;
+
+    fn prefix_len(&self) -> u8 {
+        self.prefix_len()
+    }
+
+    fn to_octets(&self) -> Self::Octets {
+        self.addr().octets()
+    }
+}
+
+impl Prefix for Ipv6Net {
+    type Octets = [u8; 16];
+    const ALEN: u8 = 128;
+
     fn prefix_len(&self) -> u8 {
         self.prefix_len()
     }
 
-    fn to_octets(&self) -> Vec<u8> {
-        self.addr().octets().to_vec()
+    fn to_octets(&self) -> Self::Octets {
+        self.addr().octets()
     }
 }
 
@@ -28,524 +60,1917 @@ impl<P, D> ArtRoot<P, D>
 where
     P: Prefix + Copy,
 {
-    pub fn new(levels: u32, bits: Vec<u8>, alen: u8) -> Self {
-        let mut ar = ArtRoot {
+    pub fn new(levels: u32, bits: Vec<u8>, alen: u8) -> Result<Self, ArtError> {
+        art_check_layout(levels, &bits, alen)?;
+        art_check_alen::<P>(alen, false)?;
+        let root = Arc::new(ArtTable::new(&bits, None));
+        Ok(ArtRoot {
             levels,
             bits,
             alen,
-            root: None,
-        };
-        let mut plen = 0u8;
-        for i in 0..levels {
-            if (i as usize) < ar.bits.len() {
-                plen += ar.bits[i as usize];
+            root,
+            len: 0,
+            observers: ArtObservers::new(),
+        })
+    }
+
+    pub fn with_layout(layout: &ArtLayout) -> Result<Self, ArtError> {
+        art_check_alen::<P>(layout.alen(), true)?;
+        ArtRoot::new(layout.levels(), layout.bits().to_vec(), layout.alen())
+    }
+
+    pub fn root(&self) -> Arc<ArtTable<P, D>> {
+        self.root.clone()
+    }
+
+    // Another handle on the same tables. Either side copies a table before
+    // modifying it, so the two never observe each other's updates. The
+    // observers are not shared.
+    pub(crate) fn share(&self) -> Self {
+        ArtRoot {
+            bits: self.bits.clone(),
+            levels: self.levels,
+            alen: self.alen,
+            root: self.root.clone(),
+            len: self.len,
+            observers: ArtObservers::new(),
+        }
+    }
+
+    // Another handle on the same tables to make a batch of updates on. The
+    // changes are held back from the observers until notify_staged().
+    pub(crate) fn stage(&self) -> Self {
+        let mut root = self.share();
+        root.observers = self.observers.hold();
+        root
+    }
+
+    // Same as stage(), keeping the changes even when no one listens so they
+    // can be replayed on another copy.
+    pub(crate) fn stage_recorded(&self) -> Self {
+        let mut root = self.share();
+        root.observers = self.observers.record();
+        root
+    }
+
+    // Stage `copy', holding the same routes as this table, instead of a
+    // copy sharing the tables of this one.
+    pub(crate) fn restage(&self, mut copy: Self) -> Self {
+        copy.observers = self.observers.record();
+        copy
+    }
+
+    // Make the changes staged on another copy of the table on this one as
+    // well. No one is told.
+    pub(crate) fn replay(&mut self, changes: &[ArtChange<P, D>]) {
+        self.observers = ArtObservers::new();
+        for change in changes {
+            match change {
+                ArtChange::Add(node) | ArtChange::Replace { new: node, .. } => {
+                    self.insert(node, &node.prefix);
+                }
+                ArtChange::Delete(node) => {
+                    self.delete(&node.prefix);
+                }
             }
         }
-        assert!(plen == alen);
-        ar.levels = levels;
-        ar.root = Some(ArtTable::new(&ar, None, 0));
-        ar
     }
 
-    pub fn new_ipv4_table() -> Self {
-        ArtRoot::new(7, [8u8, 4u8, 4u8, 4u8, 4u8, 4u8, 4u8].to_vec(), 32)
+    // Tell the observers the changes held back by a staged copy, once it
+    // has been put in place.
+    pub(crate) fn notify_staged(&self, changes: &[ArtChange<P, D>]) {
+        for change in changes.iter() {
+            self.observers.deliver(change);
+        }
     }
 
-    pub fn new_ipv6_table() -> Self {
-        ArtRoot::new(32, [4u8; 32].to_vec(), 128)
+    pub(crate) fn take_staged(&mut self) -> Vec<ArtChange<P, D>> {
+        self.observers.release()
+    }
+
+    // Call `f' with every change made to the table from now on, and return
+    // an id to unsubscribe with.
+    pub fn subscribe<F>(&mut self, f: F) -> usize
+    where
+        F: Fn(&ArtChange<P, D>) + Send + Sync + 'static,
+        D: PartialEq,
+    {
+        self.observers.subscribe(Arc::new(f), |old, new| old == new)
+    }
+
+    pub fn unsubscribe(&mut self, id: usize) -> bool {
+        self.observers.unsubscribe(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
     }
 
-    pub fn root(&self) -> Rc<ArtTable<P, D>> {
-        self.root.as_ref().unwrap().clone()
+    // Remove all routes at once. Tables still shared with another handle
+    // are left to it.
+    pub fn clear(&mut self) {
+        if self.observers.is_listening() {
+            for node in self.iter() {
+                self.observers.notify(|| ArtChange::Delete(node));
+            }
+        }
+        self.root = Arc::new(ArtTable::new(&self.bits, None));
+        self.len = 0;
     }
 
-    pub fn insert(&mut self, an: &Rc<ArtEntry<P, D>>, prefix: &P) {
+    fn check_prefix(&self, prefix: &P) -> Result<(), ArtError> {
         if prefix.prefix_len() > self.alen {
-            return;
+            return Err(ArtError::PrefixTooLong {
+                plen: prefix.prefix_len(),
+                alen: self.alen,
+            });
         }
+        Ok(())
+    }
 
-        let mut at = self.root();
+    // Same as insert() but a prefix longer than the address length is an
+    // error rather than ignored.
+    pub fn try_insert(
+        &mut self,
+        an: &Arc<ArtNode<P, D>>,
+        prefix: &P,
+    ) -> Result<Option<Arc<ArtNode<P, D>>>, ArtError> {
+        self.check_prefix(prefix)?;
+        Ok(self.insert(an, prefix))
+    }
 
-        if prefix.prefix_len() == 0 {
-            at.set_default(an.clone());
-            return;
-        }
+    pub fn try_delete(&mut self, prefix: &P) -> Result<Option<Arc<ArtNode<P, D>>>, ArtError> {
+        self.check_prefix(prefix)?;
+        Ok(self.delete(prefix))
+    }
 
-        while prefix.prefix_len() > at.offset + at.bits {
-            let j = art_findex(&at, prefix).unwrap();
+    pub fn try_lookup(&self, prefix: &P) -> Result<Option<Arc<ArtNode<P, D>>>, ArtError> {
+        self.check_prefix(prefix)?;
+        Ok(self.lookup(prefix))
+    }
 
-            let entry = at.entry[j as usize].borrow().clone();
+    // Insert the route and return the route it replaced for the same
+    // prefix, if any.
+    pub fn insert(&mut self, an: &Arc<ArtNode<P, D>>, prefix: &P) -> Option<Arc<ArtNode<P, D>>> {
+        if prefix.prefix_len() > self.alen {
+            return None;
+        }
 
-            match entry.as_ref() {
-                ArtEntry::Table(table) => {
-                    at = table.clone();
-                }
-                ArtEntry::Node(node) => {
-                    let table = ArtTable::new(self, Some(at.clone()), j);
-                    table.set_default(ArtEntry::from_node(node.clone()));
-                    at.set_entry(j, ArtEntry::from_table(table.clone()));
-                    at = table.clone();
-                }
-                ArtEntry::None => {
-                    let table = ArtTable::new(self, Some(at.clone()), j);
-                    at.set_entry(j, Rc::new(ArtEntry::Table(table.clone())));
-                    at = table.clone();
-                }
+        let addr = prefix.to_octets();
+        let at = Self::table_descend(
+            &self.bits,
+            Arc::make_mut(&mut self.root),
+            addr.as_ref(),
+            prefix.prefix_len(),
+        );
+        let i = art_bindex(at, addr.as_ref(), prefix.prefix_len()).unwrap();
+
+        let replaced = Self::table_insert(at, i, ArtEntry::Node(an.clone()));
+        match &replaced {
+            None => {
+                self.len += 1;
+                self.observers.notify(|| ArtChange::Add(an.clone()));
+            }
+            Some(old) if !Arc::ptr_eq(old, an) => {
+                self.observers.notify(|| ArtChange::Replace {
+                    old: old.clone(),
+                    new: an.clone(),
+                });
             }
+            Some(_) => {}
         }
+        replaced
+    }
 
-        let i = art_bindex(&at, prefix, prefix.prefix_len()).unwrap();
+    // Walk down from `at' to the table covering `plen', creating the
+    // missing tables on the way.
+    fn table_descend<'a>(
+        bits: &[u8],
+        mut at: &'a mut ArtTable<P, D>,
+        addr: &[u8],
+        plen: u8,
+    ) -> &'a mut ArtTable<P, D> {
+        while plen > at.offset + at.bits {
+            let j = art_findex(at, addr);
+
+            // Push the route of the fringe index, if any, down to the new
+            // table as its default.
+            if !matches!(at.get_entry(j), ArtEntry::Table(_)) {
+                let mut table = ArtTable::new(bits, Some(at));
+                table.set_default(at.get_entry(j).clone());
+                at.set_entry(j, ArtEntry::Table(Arc::new(table)));
+                at.refcnt += 1;
+            }
 
-        self.table_insert(&at, i, an.clone());
+            at = match at.get_entry_mut(j) {
+                ArtEntry::Table(table) => Arc::make_mut(table),
+                _ => unreachable!(),
+            };
+        }
+        at
     }
 
-    fn table_insert(&mut self, at: &ArtTable<P, D>, i: u32, an: Rc<ArtEntry<P, D>>) {
-        let mut prev = at.get_entry(i);
+    fn table_insert(
+        at: &mut ArtTable<P, D>,
+        i: u64,
+        an: ArtEntry<P, D>,
+    ) -> Option<Arc<ArtNode<P, D>>> {
+        let prev = match at.get_entry(i) {
+            ArtEntry::Table(table) => table.get_default().clone(),
+            entry => entry.clone(),
+        };
 
-        if ArtEntry::check_duplicate(&prev, &an) {
-            return;
+        // The route for the same prefix is the only one held at index `i',
+        // anything else there is a less specific route alloted to it.
+        // Replacing it leaves the indices it is alloted to untouched.
+        if let ArtEntry::Node(node) = &prev {
+            if ArtEntry::is_same(&prev, &an) {
+                return prev.node();
+            }
+            let replaced = node.clone();
+            match at.get_entry_mut(i) {
+                ArtEntry::Table(table) => Arc::make_mut(table).set_default(an),
+                entry => *entry = an,
+            }
+            return Some(replaced);
         }
 
+        at.refcnt += 1;
+
         // If the index `i' of the route that we are inserting is not a fringe
-        // index, we need to allot this new route pointer to all the fringe
-        // indices.
-        if i < at.minfringe {
-            if let ArtEntry::Table(table) = prev.as_ref() {
-                prev = table.get_default();
-            }
-            art_allot(at, i, prev, &an);
-        } else if let ArtEntry::Table(table) = prev.as_ref() {
-            table.set_default(an.clone());
+        // index, we need to allot this new route to all the fringe indices.
+        // The default route of the root table is not alloted.
+        if i == 1 {
+            at.set_default(an);
+        } else if i < at.minfringe {
+            at.set_entry(i, an);
+            art_allot(at, i << 1, &prev, &ArtEntry::Alloted(i));
+            art_allot(at, (i << 1) + 1, &prev, &ArtEntry::Alloted(i));
+        } else if let ArtEntry::Table(table) = at.get_entry_mut(i) {
+            Arc::make_mut(table).set_default(an);
         } else {
-            at.set_entry(i, an.clone())
+            at.set_entry(i, an)
         }
+        None
     }
 
-    pub fn lookup(&self, prefix: &P) -> Option<Rc<ArtNode<P, D>>> {
-        let mut at = self.root();
+    pub fn lookup(&self, prefix: &P) -> Option<Arc<ArtNode<P, D>>> {
+        let addr = prefix.to_octets();
+        let mut at = self.root.as_ref();
         let mut default = at.get_default();
 
         while prefix.prefix_len() > at.offset + at.bits {
-            let j = art_findex(&at, prefix).unwrap();
-            let entry = at.entry[j as usize].borrow().clone();
+            let j = art_findex(at, addr.as_ref());
 
-            match entry.as_ref() {
+            match at.get_entry(j) {
                 ArtEntry::Table(table) => {
-                    at = table.clone();
-                    if at.has_default() {
-                        default = at.get_default();
+                    if let Some(route) = at.get_table_default(j, table) {
+                        default = route;
                     }
+                    at = table;
                 }
-                ArtEntry::Node(node) => {
-                    return Some(node.clone());
-                }
-                ArtEntry::None => {
-                    if let ArtEntry::Node(node) = default.as_ref() {
-                        return Some(node.clone());
-                    } else {
-                        return None;
-                    }
+                _ => {
+                    return at.get_route(j).node().or_else(|| default.node());
                 }
             }
         }
 
-        let i = art_bindex(&at, prefix, prefix.prefix_len()).unwrap();
-        let entry = at.get_entry(i);
+        let i = art_bindex(at, addr.as_ref(), prefix.prefix_len()).unwrap();
 
-        match entry.as_ref() {
-            ArtEntry::Node(node) => {
-                return Some(node.clone());
-            }
-            ArtEntry::Table(table) => {
-                if let ArtEntry::Node(node) = table.get_default().as_ref() {
-                    return Some(node.clone());
+        let route = match at.get_entry(i) {
+            ArtEntry::Table(table) => at.get_table_default(i, table),
+            _ => Some(at.get_route(i)),
+        };
+        route
+            .and_then(|route| route.node())
+            .or_else(|| default.node())
+    }
+
+    // Longest match lookup of a full length address given as octets. Every
+    // level is indexed with its fringe index, so no prefix length check is
+    // needed on the way down.
+    pub(crate) fn lookup_octets(&self, addr: &[u8]) -> Option<Arc<ArtNode<P, D>>> {
+        let mut at = self.root.as_ref();
+        let mut default = at.get_default();
+
+        loop {
+            let j = art_findex(at, addr);
+
+            match at.get_entry(j) {
+                ArtEntry::Table(table) => {
+                    if let Some(route) = at.get_table_default(j, table) {
+                        default = route;
+                    }
+                    at = table;
+                }
+                _ => {
+                    return at.get_route(j).node().or_else(|| default.node());
                 }
             }
-            ArtEntry::None => {}
         }
-        if let ArtEntry::Node(node) = default.as_ref() {
-            return Some(node.clone());
-        }
-        None
     }
 
-    pub fn lookup_exact(&self, prefix: &P) -> Option<Rc<ArtNode<P, D>>> {
-        let mut at = self.root();
+    pub fn lookup_exact(&self, prefix: &P) -> Option<Arc<ArtNode<P, D>>> {
+        let addr = prefix.to_octets();
+        let mut at = self.root.as_ref();
 
         while prefix.prefix_len() > at.offset + at.bits {
-            let j = art_findex(&at, prefix).unwrap();
-            let entry = at.entry[j as usize].borrow().clone();
+            let j = art_findex(at, addr.as_ref());
 
-            match entry.as_ref() {
+            match at.get_entry(j) {
                 ArtEntry::Table(table) => {
-                    at = table.clone();
+                    at = table;
                 }
-                ArtEntry::Node(_) | ArtEntry::None => {
+                ArtEntry::Node(_) | ArtEntry::Alloted(_) | ArtEntry::None => {
                     return None;
                 }
             }
         }
 
-        let i = art_bindex(&at, prefix, prefix.prefix_len()).unwrap();
-        let entry = at.get_entry(i);
+        let i = art_bindex(at, addr.as_ref(), prefix.prefix_len()).unwrap();
 
-        match entry.as_ref() {
-            ArtEntry::Node(node) => {
-                if node.prefix.prefix_len() == prefix.prefix_len() {
-                    return Some(node.clone());
-                }
-            }
-            ArtEntry::Table(table) => {
-                if let ArtEntry::Node(node) = table.get_default().as_ref() {
-                    if node.prefix.prefix_len() == prefix.prefix_len() {
-                        return Some(node.clone());
-                    }
-                }
-            }
-            ArtEntry::None => {}
+        // Only the route for the prefix is held at its base index.
+        match at.get_entry(i) {
+            ArtEntry::Table(table) => table.get_default().node(),
+            entry => entry.node(),
         }
-        None
     }
 
-    pub fn delete(&mut self, prefix: &P) {
+    // Delete the route for the prefix and return it.
+    pub fn delete(&mut self, prefix: &P) -> Option<Arc<ArtNode<P, D>>> {
         if prefix.prefix_len() > self.alen {
-            return;
+            return None;
         }
 
-        let mut at = self.root();
-
-        while prefix.prefix_len() > at.offset + at.bits {
-            let j = art_findex(&at, prefix).unwrap();
-            let entry = at.entry[j as usize].borrow().clone();
+        // Make sure the route exists before tables are copied on the way
+        // down.
+        self.lookup_exact(prefix)?;
+
+        let addr = prefix.to_octets();
+        let node = Self::table_delete(
+            Arc::make_mut(&mut self.root),
+            addr.as_ref(),
+            prefix.prefix_len(),
+        );
+        if let Some(node) = &node {
+            self.len -= 1;
+            self.observers.notify(|| ArtChange::Delete(node.clone()));
+        }
+        node
+    }
 
-            match entry.as_ref() {
-                ArtEntry::Table(table) => {
-                    at = table.clone();
-                }
-                ArtEntry::Node(_) | ArtEntry::None => {
-                    return;
-                }
+    fn table_delete(at: &mut ArtTable<P, D>, addr: &[u8], plen: u8) -> Option<Arc<ArtNode<P, D>>> {
+        if plen > at.offset + at.bits {
+            let j = art_findex(at, addr);
+            let ArtEntry::Table(table) = at.get_entry_mut(j) else {
+                return None;
+            };
+            let table = Arc::make_mut(table);
+            let node = Self::table_delete(table, addr, plen);
+
+            // Detach the table once it no longer holds any route or
+            // sub-table. The fringe index gets back the route that was
+            // pushed down as the table default.
+            if table.refcnt == 0 {
+                let default = table.get_default().clone();
+                at.set_entry(j, default);
+                at.refcnt -= 1;
             }
+            return node;
         }
 
-        let i = art_bindex(&at, prefix, prefix.prefix_len()).unwrap();
-        let mut prev = at.get_entry(i);
-
-        let next = if (i >> 1) > 1 {
-            at.get_entry(i >> 1)
-        } else {
-            ArtEntry::none()
+        let i = art_bindex(at, addr, plen).unwrap();
+        let prev = match at.get_entry(i) {
+            ArtEntry::Table(table) => table.get_default().clone(),
+            entry => entry.clone(),
         };
 
+        // Nothing to delete when the index only holds a less specific route
+        // alloted to it.
+        if !matches!(prev, ArtEntry::Node(_)) {
+            return None;
+        }
+
+        // The indices covered by the route go to the route covering its own
+        // index, if any.
+        let next = art_covering(at, i >> 1);
+
         if i < at.minfringe {
-            if let ArtEntry::Table(table) = prev.as_ref() {
-                prev = table.get_default();
-            }
-            art_allot(&at, i, prev, &next);
+            at.set_entry(i, next.clone());
+            art_allot(at, i << 1, &ArtEntry::Alloted(i), &next);
+            art_allot(at, (i << 1) + 1, &ArtEntry::Alloted(i), &next);
+        } else if let ArtEntry::Table(table) = at.get_entry_mut(i) {
+            Arc::make_mut(table).set_default(next);
         } else {
-            match prev.as_ref() {
-                ArtEntry::Table(table) => {
-                    if table.has_default() {
-                        table.set_default(ArtEntry::none());
-                    }
+            at.set_entry(i, next);
+        }
+
+        at.refcnt -= 1;
+
+        prev.node()
+    }
+
+    // Delete every route for which `f' returns true, in a single walk over
+    // the tables, and return them.
+    pub fn drain_filter<F>(&mut self, mut f: F) -> Vec<Arc<ArtNode<P, D>>>
+    where
+        F: FnMut(&P, Option<&D>) -> bool,
+    {
+        let mut removed = Vec::new();
+        if Self::table_drain(&mut self.root, &ArtEntry::None, &mut f, &mut removed) {
+            Arc::make_mut(&mut self.root).refcnt -= 1;
+        }
+        self.len -= removed.len();
+        for node in removed.iter() {
+            self.observers.notify(|| ArtChange::Delete(node.clone()));
+        }
+        removed
+    }
+
+    // Keep only the routes for which `f' returns true.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&P, Option<&D>) -> bool,
+    {
+        self.drain_filter(|prefix, data| !f(prefix, data));
+    }
+
+    // Indices are visited in increasing order, so the route covering an
+    // index is settled before the index itself. Every index not holding a
+    // route of its own gets the route covering it again, which fixes up the
+    // indices alloted to a deleted route. `default' is the route covering
+    // the fringe index of the table in its parent. The table is only copied
+    // if something changes in it.
+    //
+    // Return whether the route held as the table default has been deleted,
+    // which is accounted for in the parent table.
+    fn table_drain<F>(
+        at: &mut Arc<ArtTable<P, D>>,
+        default: &ArtEntry<P, D>,
+        f: &mut F,
+        removed: &mut Vec<Arc<ArtNode<P, D>>>,
+    ) -> bool
+    where
+        F: FnMut(&P, Option<&D>) -> bool,
+    {
+        let mut default_removed = false;
+
+        // Only the indices holding something are visited in a sparse
+        // table, nothing being alloted in it.
+        let (range, held) = match at.held_indices() {
+            Some(held) => (0..0, held),
+            None => (1..(at.minfringe << 1), Vec::new()),
+        };
+        for i in range.chain(held) {
+            if matches!(at.get_entry(i), ArtEntry::Table(_)) {
+                let next = art_covering(at, i >> 1);
+
+                // Take the sub-table out of a table that is not shared, so
+                // that it is not copied in turn.
+                let mut entry = match Arc::get_mut(at) {
+                    Some(at) => std::mem::replace(at.get_entry_mut(i), ArtEntry::None),
+                    None => at.get_entry(i).clone(),
+                };
+                let ArtEntry::Table(table) = &mut entry else {
+                    unreachable!();
+                };
+                let fringe_removed = Self::table_drain(table, &next, f, removed);
+                let empty = table.refcnt == 0;
+                let unchanged =
+                    matches!(at.get_entry(i), ArtEntry::Table(prev) if Arc::ptr_eq(prev, table));
+
+                if !unchanged {
+                    Arc::make_mut(at).set_entry(i, entry);
+                }
+                if fringe_removed {
+                    Arc::make_mut(at).refcnt -= 1;
+                }
+                // Detach the table once it no longer holds any route or
+                // sub-table, as on delete.
+                if empty {
+                    let at = Arc::make_mut(at);
+                    let ArtEntry::Table(table) = at.get_entry(i) else {
+                        unreachable!();
+                    };
+                    let default = table.get_default().clone();
+                    at.set_entry(i, default);
+                    at.refcnt -= 1;
+                }
+                continue;
+            }
+
+            let next = if i == 1 {
+                default.clone()
+            } else {
+                art_covering(at, i >> 1)
+            };
+            let entry = at.get_entry(i);
+            if let ArtEntry::Node(node) = entry {
+                if !f(&node.prefix, node.data.as_ref()) {
+                    continue;
                 }
-                ArtEntry::Node(_) => {
-                    at.set_entry(i, ArtEntry::none());
+                removed.push(node.clone());
+                let at = Arc::make_mut(at);
+                at.set_entry(i, next);
+                if i == 1 && at.level > 0 {
+                    default_removed = true;
+                } else {
+                    at.refcnt -= 1;
                 }
-                ArtEntry::None => {}
+            } else if !ArtEntry::is_same(entry, &next) {
+                Arc::make_mut(at).set_entry(i, next);
             }
         }
+        default_removed
     }
 
     pub fn iter(&self) -> ArtIter<P, D> {
         ArtIter {
-            i: 1,
+            stack: Vec::new(),
             at: self.root(),
+            pos: 0,
         }
     }
 
-    pub fn route_ipv4_add(&mut self, prefix: P, data: D) {
-        // let prefix: P = str.parse().unwrap();
-        let node = Rc::new(ArtEntry::Node(Rc::new(ArtNode {
-            data: Some(data),
-            prefix,
-        })));
-        self.insert(&node, &prefix);
+    // Iterate the routes in prefix order, by address and then by prefix
+    // length.
+    pub fn iter_sorted(&self) -> ArtSortedIter<P, D> {
+        ArtSortedIter {
+            stack: vec![(self.root(), 1)],
+        }
     }
 
-    pub fn route_ipv4_delete(&mut self, prefix: P) {
-        self.delete(&prefix);
+    // Iterate the routes more specific than the prefix, in prefix order.
+    // Only the tables below the prefix are visited.
+    pub fn iter_more_specifics(&self, prefix: &P) -> ArtSortedIter<P, D> {
+        let mut iter = ArtSortedIter { stack: Vec::new() };
+        if let Some((at, i)) = self.subtree(prefix) {
+            iter.push_children(&at, i);
+        }
+        iter
     }
 
-    pub fn route_ipv4_lookup(&self, prefix: P) -> Option<Rc<ArtNode<P, D>>> {
-        self.lookup(&prefix)
+    // Same as iter_more_specifics() but starting with the route for the
+    // prefix itself, if any.
+    pub fn iter_more_specifics_inclusive(&self, prefix: &P) -> ArtSortedIter<P, D> {
+        ArtSortedIter {
+            stack: self.subtree(prefix).into_iter().collect(),
+        }
     }
 
-    pub fn route_ipv4_lookup_exact(&self, prefix: P) -> Option<Rc<ArtNode<P, D>>> {
-        self.lookup_exact(&prefix)
+    // Iterate the routes covering the prefix, from the least specific down
+    // to the route for the prefix itself, if any. Every prefix length is
+    // checked at its base index along the lookup path.
+    pub fn iter_less_specifics(&self, prefix: &P) -> std::vec::IntoIter<Arc<ArtNode<P, D>>> {
+        let mut routes = Vec::new();
+        let plen = prefix.prefix_len().min(self.alen);
+        let addr = prefix.to_octets();
+        let mut at = self.root.as_ref();
+        let mut len = 0;
+
+        loop {
+            // Index 1 of a sub-table is the fringe index of its parent,
+            // which has already been checked.
+            while len <= plen.min(at.offset + at.bits) {
+                let i = art_bindex(at, addr.as_ref(), len).unwrap();
+                let entry = match at.get_entry(i) {
+                    ArtEntry::Table(table) => table.get_default(),
+                    entry => entry,
+                };
+                routes.extend(entry.node());
+                len += 1;
+            }
+
+            if plen <= at.offset + at.bits {
+                break;
+            }
+            match at.get_entry(art_findex(at, addr.as_ref())) {
+                ArtEntry::Table(table) => at = table,
+                _ => break,
+            }
+        }
+        routes.into_iter()
     }
 
-    // pub fn route_ipv6_add(&mut self, str: &str, data: D) {
-    //     self.route_ipv4_add(str, data);
-    // }
+    // The table and the base index of the prefix, if the table exists.
+    fn subtree(&self, prefix: &P) -> Option<(Arc<ArtTable<P, D>>, u64)> {
+        if prefix.prefix_len() > self.alen {
+            return None;
+        }
 
-    // pub fn route_ipv6_delete(&mut self, str: &str) {
-    //     self.route_ipv4_delete(str);
-    // }
+        let addr = prefix.to_octets();
+        let mut at = &self.root;
 
-    // pub fn route_ipv6_lookup(&self, str: &str) -> Option<Rc<ArtNode<P, D>>> {
-    //     self.route_ipv4_lookup(str)
-    // }
+        while prefix.prefix_len() > at.offset + at.bits {
+            let j = art_findex(at, addr.as_ref());
 
-    // pub fn route_ipv6_lookup_exact(&self, str: &str) -> Option<Rc<ArtNode<P, D>>> {
-    //     self.route_ipv4_lookup_exact(str)
-    // }
-}
+            match at.get_entry(j) {
+                ArtEntry::Table(table) => {
+                    at = table;
+                }
+                ArtEntry::Node(_) | ArtEntry::Alloted(_) | ArtEntry::None => {
+                    return None;
+                }
+            }
+        }
 
-pub struct ArtTable<P, D> {
-    minfringe: u32,
-    level: u32,
-    index: u32,
-    bits: u8,
-    offset: u8,
-    parent: Option<Rc<ArtTable<P, D>>>,
-    entry: Vec<RefCell<Rc<ArtEntry<P, D>>>>,
-}
+        let i = art_bindex(at, addr.as_ref(), prefix.prefix_len()).unwrap();
+        Some((at.clone(), i))
+    }
 
-impl<P, D> ArtTable<P, D> {
-    fn new(root: &ArtRoot<P, D>, parent: Option<Rc<ArtTable<P, D>>>, j: u32) -> Rc<Self> {
-        let mut table = ArtTable {
-            minfringe: 0,
-            level: 0,
-            index: j,
-            bits: 0,
-            offset: 0,
-            parent: parent.clone(),
-            entry: Vec::new(),
-        };
-        let level = if let Some(parent) = parent.as_ref() {
-            table.offset = parent.offset + parent.bits;
-            parent.level + 1
-        } else {
-            0
-        };
-        table.minfringe = 1 << root.bits[level as usize];
-        table.level = level;
-        table.bits = root.bits[level as usize];
-        table.entry = vec![RefCell::new(Rc::new(ArtEntry::None)); (table.minfringe << 1) as usize];
-        Rc::new(table)
+    pub fn route_ipv4_add(&mut self, prefix: P, data: D) -> Option<Arc<ArtNode<P, D>>> {
+        // let prefix: P = str.parse().unwrap();
+        let node = Arc::new(ArtNode {
+            data: Some(data),
+            prefix,
+        });
+        self.insert(&node, &prefix)
+    }
+
+    pub fn route_ipv4_delete(&mut self, prefix: P) -> Option<Arc<ArtNode<P, D>>> {
+        self.delete(&prefix)
     }
 
-    pub fn get_entry(&self, i: u32) -> Rc<ArtEntry<P, D>> {
-        self.entry[i as usize].borrow().clone()
+    pub fn route_ipv4_lookup(&self, prefix: P) -> Option<Arc<ArtNode<P, D>>> {
+        self.lookup(&prefix)
     }
 
-    fn set_entry(&self, i: u32, an: Rc<ArtEntry<P, D>>) {
-        self.entry[i as usize].replace(an);
+    pub fn route_ipv4_lookup_exact(&self, prefix: P) -> Option<Arc<ArtNode<P, D>>> {
+        self.lookup_exact(&prefix)
     }
 
-    fn has_default(&self) -> bool {
-        matches!(self.entry[1].borrow().as_ref(), ArtEntry::Node(_))
+    pub fn route_ipv6_add(&mut self, prefix: P, data: D) -> Option<Arc<ArtNode<P, D>>> {
+        self.route_ipv4_add(prefix, data)
     }
 
-    fn get_default(&self) -> Rc<ArtEntry<P, D>> {
-        self.entry[1].borrow().clone()
+    pub fn route_ipv6_delete(&mut self, prefix: P) -> Option<Arc<ArtNode<P, D>>> {
+        self.route_ipv4_delete(prefix)
     }
 
-    fn set_default(&self, an: Rc<ArtEntry<P, D>>) {
-        self.entry[1].replace(an);
+    pub fn route_ipv6_lookup(&self, prefix: P) -> Option<Arc<ArtNode<P, D>>> {
+        self.route_ipv4_lookup(prefix)
     }
-}
 
-pub struct ArtIter<P, D> {
-    at: Rc<ArtTable<P, D>>,
-    i: usize,
+    pub fn route_ipv6_lookup_exact(&self, prefix: P) -> Option<Arc<ArtNode<P, D>>> {
+        self.route_ipv4_lookup_exact(prefix)
+    }
 }
 
-impl<P, D> IntoIterator for &ArtRoot<P, D>
+// Routes are modified in place when nothing else holds them. A route still
+// held by a lookup result or by another handle on the tables is copied
+// first, together with the tables on its path. The observers are told the
+// route has been replaced once the data is no longer borrowed.
+impl<P, D> ArtRoot<P, D>
 where
     P: Prefix + Copy,
+    D: Clone,
 {
-    type Item = Rc<ArtNode<P, D>>;
-    type IntoIter = ArtIter<P, D>;
+    pub fn lookup_exact_mut(&mut self, prefix: &P) -> Option<ArtDataMut<'_, P, D>> {
+        if prefix.prefix_len() > self.alen {
+            return None;
+        }
 
-    fn into_iter(self) -> Self::IntoIter {
-        ArtIter {
-            i: 1,
-            at: self.root(),
+        // Make sure the route exists before tables are copied on the way
+        // down.
+        self.lookup_exact(prefix)?;
+
+        let addr = prefix.to_octets();
+        let mut at = Arc::make_mut(&mut self.root);
+
+        while prefix.prefix_len() > at.offset + at.bits {
+            let j = art_findex(at, addr.as_ref());
+            at = match at.get_entry_mut(j) {
+                ArtEntry::Table(table) => Arc::make_mut(table),
+                _ => return None,
+            };
+        }
+
+        let node = art_route_mut(at, addr.as_ref(), prefix.prefix_len());
+        node.data.as_ref()?;
+        Some(ArtDataMut::new(node, &self.observers))
+    }
+
+    pub fn get_mut(&mut self, prefix: &P) -> Option<ArtDataMut<'_, P, D>> {
+        self.lookup_exact_mut(prefix)
+    }
+
+    // Iterate the routes with their data, in the order of iter(). Routes
+    // without data are skipped.
+    pub fn iter_mut(&mut self) -> ArtIterMut<'_, P, D> {
+        ArtIterMut {
+            all: self.iter_mut_all(),
+        }
+    }
+
+    // Like iter_mut(), with the routes without data yielded with None.
+    pub fn iter_mut_all(&mut self) -> ArtIterMutAll<'_, P, D> {
+        ArtIterMutAll {
+            stack: vec![Arc::make_mut(&mut self.root).entries_mut()],
+            observers: &self.observers,
+        }
+    }
+
+    // Like into_iter(), with the routes without data yielded with None.
+    pub fn into_routes(self) -> ArtIntoRoutes<P, D> {
+        ArtIntoRoutes {
+            stack: vec![art_into_entries(self.root).into_iter()],
         }
     }
 }
 
-impl<P, D> Iterator for ArtIter<P, D>
+impl<P, D> ArtRoot<P, D>
 where
     P: Prefix + Copy,
+    D: Clone,
 {
-    type Item = Rc<ArtNode<P, D>>;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            while self.i < (self.at.minfringe << 1) as usize {
-                let entry = self.at.get_entry(self.i as u32);
-                match entry.as_ref() {
-                    ArtEntry::Node(node) => {
-                        if let Some(j) =
-                            art_bindex(&self.at, &node.prefix, node.prefix.prefix_len())
-                        {
-                            if self.i == j as usize {
-                                self.i += 1;
-                                return Some(node.clone());
-                            }
-                        }
-                        self.i += 1;
-                    }
-                    ArtEntry::Table(table) => {
-                        self.at = table.clone();
-                        self.i = 1;
-                    }
-                    ArtEntry::None => {
-                        self.i += 1;
-                    }
-                }
+    // Entry for the prefix, found with a single walk down the tables.
+    //
+    // The entry keeps the deepest table on the path that would still hold
+    // something once the route is removed, so that remove() can reclaim the
+    // tables below it. The tables between it and the route only hold the
+    // path itself and are walked again when the route is accessed.
+    pub fn entry(&mut self, prefix: P) -> Result<ArtRouteEntry<'_, P, D>, ArtError> {
+        self.check_prefix(&prefix)?;
+
+        let plen = prefix.prefix_len();
+        let addr = prefix.to_octets();
+        let len = &mut self.len;
+        let observers = &mut self.observers;
+        let mut anchor = Arc::make_mut(&mut self.root);
+        let mut depth = 0;
+
+        let occupied = loop {
+            let at = art_walk(anchor, addr.as_ref(), depth);
+            if plen <= at.offset + at.bits {
+                let i = art_bindex(at, addr.as_ref(), plen).unwrap();
+                break matches!(art_route_entry(at, i), ArtEntry::Node(_));
             }
-            if let Some(parent) = &self.at.parent {
-                self.i = (self.at.index + 1) as usize;
-                self.at = parent.clone();
-            } else {
-                break;
+            match at.get_entry(art_findex(at, addr.as_ref())) {
+                ArtEntry::Table(table) if table.refcnt > 1 => {
+                    anchor = art_walk_mut(anchor, addr.as_ref(), depth + 1);
+                    depth = 0;
+                }
+                ArtEntry::Table(_) => depth += 1,
+                _ => break false,
             }
+        };
+
+        if occupied {
+            Ok(ArtRouteEntry::Occupied(ArtOccupiedEntry {
+                len,
+                observers,
+                anchor,
+                depth,
+                prefix,
+            }))
+        } else {
+            Ok(ArtRouteEntry::Vacant(ArtVacantEntry {
+                bits: &self.bits,
+                len,
+                observers,
+                at: art_walk_mut(anchor, addr.as_ref(), depth),
+                prefix,
+            }))
         }
-        None
     }
 }
 
-pub struct ArtNode<P, D> {
-    pub prefix: P,
-    pub data: Option<D>,
-}
-
-impl<P, D> ArtNode<P, D>
+impl<P, D> ArtRoot<P, D>
 where
     P: Prefix + Copy,
+    D: PartialEq,
 {
-    pub fn new(prefix: &P, data: Option<D>) -> Rc<Self> {
-        Rc::new(Self {
-            prefix: *prefix,
-            data,
-        })
+    // Changes turning this table into `other', in prefix order: routes
+    // only in `other' are added, routes only in this table deleted and
+    // routes with different data replaced.
+    //
+    // Tables of the same layout are walked in lockstep, skipping the
+    // tables they still share, so comparing a table with a snapshot of it
+    // only visits the paths updated since. Otherwise the routes of both
+    // are merged in prefix order.
+    pub fn diff(&self, other: &ArtRoot<P, D>) -> ArtDiff<P, D> {
+        if self.bits != other.bits {
+            return ArtDiff {
+                stack: Vec::new(),
+                merge: Some(ArtDiffMerge {
+                    old: self.iter_sorted(),
+                    new: other.iter_sorted(),
+                    old_next: None,
+                    new_next: None,
+                }),
+            };
+        }
+        let mut stack = Vec::new();
+        if !Arc::ptr_eq(&self.root, &other.root) {
+            stack.push((Some((self.root(), 1)), Some((other.root(), 1))));
+        }
+        ArtDiff { stack, merge: None }
     }
 }
 
-pub enum ArtEntry<P, D> {
-    Table(Rc<ArtTable<P, D>>),
-    Node(Rc<ArtNode<P, D>>),
-    None,
+pub enum ArtRouteEntry<'a, P, D> {
+    Occupied(ArtOccupiedEntry<'a, P, D>),
+    Vacant(ArtVacantEntry<'a, P, D>),
 }
 
-impl<P, D> ArtEntry<P, D> {
-    fn none() -> Rc<ArtEntry<P, D>> {
-        Rc::new(ArtEntry::None)
+pub struct ArtOccupiedEntry<'a, P, D> {
+    len: &'a mut usize,
+    observers: &'a mut ArtObservers<P, D>,
+    anchor: &'a mut ArtTable<P, D>,
+    // Number of tables from the anchor down to the table of the route.
+    depth: usize,
+    prefix: P,
+}
+
+pub struct ArtVacantEntry<'a, P, D> {
+    bits: &'a [u8],
+    len: &'a mut usize,
+    observers: &'a mut ArtObservers<P, D>,
+    // Deepest table on the path of the prefix.
+    at: &'a mut ArtTable<P, D>,
+    prefix: P,
+}
+
+impl<'a, P, D> ArtRouteEntry<'a, P, D>
+where
+    P: Prefix + Copy,
+    D: Clone,
+{
+    pub fn key(&self) -> &P {
+        match self {
+            ArtRouteEntry::Occupied(entry) => entry.key(),
+            ArtRouteEntry::Vacant(entry) => entry.key(),
+        }
     }
 
-    pub fn from_node(node: Rc<ArtNode<P, D>>) -> Rc<ArtEntry<P, D>> {
-        Rc::new(ArtEntry::Node(node))
+    pub fn or_insert(self, data: D) -> ArtDataMut<'a, P, D> {
+        self.or_insert_with(|| data)
     }
 
-    pub fn from_table(node: Rc<ArtTable<P, D>>) -> Rc<ArtEntry<P, D>> {
-        Rc::new(ArtEntry::Table(node))
+    // A route without data gets the new data as well.
+    pub fn or_insert_with<F: FnOnce() -> D>(self, f: F) -> ArtDataMut<'a, P, D> {
+        match self {
+            ArtRouteEntry::Occupied(mut entry) => {
+                if entry.get().is_none() {
+                    entry.insert(f());
+                }
+                entry.into_mut().unwrap()
+            }
+            ArtRouteEntry::Vacant(entry) => entry.insert(f()),
+        }
     }
 
-    fn check_duplicate(old: &Rc<ArtEntry<P, D>>, new: &Rc<ArtEntry<P, D>>) -> bool {
-        std::ptr::eq(old.as_ref(), new.as_ref())
+    pub fn and_modify<F: FnOnce(&mut D)>(mut self, f: F) -> Self {
+        if let ArtRouteEntry::Occupied(entry) = &mut self {
+            if let Some(mut data) = entry.get_mut() {
+                f(&mut data);
+            }
+        }
+        self
     }
 }
 
-// pub fn to_octets(ipnet: &IpNet) -> Vec<u8> {
-//     match ipnet {
-//         IpNet::V4(v4net) => v4net.addr().octets().to_vec(),
-//         IpNet::V6(v6net) => v6net.addr().octets().to_vec(),
-//     }
-// }
-
-// Return the base index of the part of ``addr'' and ``plen''
-// corresponding to the range covered by the table ``at''.
-//
-// In other words, this function take the multi-level (complete)
-// address ``addr'' and prefix length ``plen'' and return the
-// single level base index for the table ``at''.
-//
-// For example with an address size of 32bit divided into four
-// 8bit-long tables, there's a maximum of 4 base indexes if the
-// prefix length is > 24.
-//
-fn art_bindex<P, D>(at: &ArtTable<P, D>, prefix: &P, mut plen: u8) -> Option<u32>
+impl<'a, P, D> ArtOccupiedEntry<'a, P, D>
 where
     P: Prefix + Copy,
+    D: Clone,
 {
-    let mut k: u32;
-    //let mut plen = prefix.prefix_len();
+    pub fn key(&self) -> &P {
+        &self.prefix
+    }
 
-    if plen < at.offset || plen > (at.offset + at.bits) {
-        return None;
+    pub fn get(&self) -> Option<&D> {
+        let addr = self.prefix.to_octets();
+        let at = art_walk(self.anchor, addr.as_ref(), self.depth);
+        let i = art_bindex(at, addr.as_ref(), self.prefix.prefix_len()).unwrap();
+        match art_route_entry(at, i) {
+            ArtEntry::Node(node) => node.data.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self) -> Option<ArtDataMut<'_, P, D>> {
+        let addr = self.prefix.to_octets();
+        let at = art_walk_mut(self.anchor, addr.as_ref(), self.depth);
+        let node = art_route_mut(at, addr.as_ref(), self.prefix.prefix_len());
+        node.data.as_ref()?;
+        Some(ArtDataMut::new(node, self.observers))
+    }
+
+    pub fn into_mut(self) -> Option<ArtDataMut<'a, P, D>> {
+        let addr = self.prefix.to_octets();
+        let at = art_walk_mut(self.anchor, addr.as_ref(), self.depth);
+        let node = art_route_mut(at, addr.as_ref(), self.prefix.prefix_len());
+        node.data.as_ref()?;
+        Some(ArtDataMut::new(node, self.observers))
+    }
+
+    // Replace the data of the route and return the previous data.
+    pub fn insert(&mut self, data: D) -> Option<D> {
+        if !self.observers.is_listening() {
+            return self.node_mut().data.replace(data);
+        }
+        let old = self.node();
+        let prev = self.node_mut().data.replace(data);
+        let new = self.node();
+        self.observers.notify(|| ArtChange::Replace { old, new });
+        prev
+    }
+
+    pub fn remove(self) -> Arc<ArtNode<P, D>> {
+        let addr = self.prefix.to_octets();
+        *self.len -= 1;
+        let node =
+            ArtRoot::table_delete(self.anchor, addr.as_ref(), self.prefix.prefix_len()).unwrap();
+        self.observers.notify(|| ArtChange::Delete(node.clone()));
+        node
+    }
+
+    fn node(&self) -> Arc<ArtNode<P, D>> {
+        let addr = self.prefix.to_octets();
+        let at = art_walk(self.anchor, addr.as_ref(), self.depth);
+        let i = art_bindex(at, addr.as_ref(), self.prefix.prefix_len()).unwrap();
+        art_route_entry(at, i).node().unwrap()
+    }
+
+    fn node_mut(&mut self) -> &mut ArtNode<P, D> {
+        let addr = self.prefix.to_octets();
+        let at = art_walk_mut(self.anchor, addr.as_ref(), self.depth);
+        Arc::make_mut(art_route_mut(at, addr.as_ref(), self.prefix.prefix_len()))
+    }
+}
+
+impl<'a, P, D> ArtVacantEntry<'a, P, D>
+where
+    P: Prefix + Copy,
+    D: Clone,
+{
+    pub fn key(&self) -> &P {
+        &self.prefix
+    }
+
+    pub fn insert(self, data: D) -> ArtDataMut<'a, P, D> {
+        let addr = self.prefix.to_octets();
+        let plen = self.prefix.prefix_len();
+        let at = ArtRoot::table_descend(self.bits, self.at, addr.as_ref(), plen);
+        let i = art_bindex(at, addr.as_ref(), plen).unwrap();
+
+        let node = ArtNode::new(&self.prefix, Some(data));
+        ArtRoot::table_insert(at, i, ArtEntry::Node(node.clone()));
+        *self.len += 1;
+        self.observers.notify(|| ArtChange::Add(node));
+        ArtDataMut::new(art_route_mut(at, addr.as_ref(), plen), self.observers)
+    }
+}
+
+impl<D> ArtRoot<Ipv4Net, D> {
+    pub fn new_ipv4_table() -> Self {
+        ArtRoot::with_layout(&ArtLayout::ipv4_8_4_4_4_4_4_4()).unwrap()
+    }
+
+    pub fn lookup_addr(&self, addr: Ipv4Addr) -> Option<Arc<ArtNode<Ipv4Net, D>>> {
+        self.lookup_octets(&addr.octets())
+    }
+}
+
+impl<D> ArtRoot<Ipv6Net, D> {
+    pub fn new_ipv6_table() -> Self {
+        ArtRoot::with_layout(&ArtLayout::ipv6_4x32()).unwrap()
+    }
+
+    pub fn lookup_addr(&self, addr: Ipv6Addr) -> Option<Arc<ArtNode<Ipv6Net, D>>> {
+        self.lookup_octets(&addr.octets())
+    }
+}
+
+pub struct ArtTable<P, D> {
+    minfringe: u64,
+    level: u32,
+    bits: u8,
+    offset: u8,
+    // Number of routes and sub-tables held by this table.
+    refcnt: u32,
+    entry: ArtEntries<P, D>,
+}
+
+// Entries of a table. A table up to ART_DENSE_STRIDE bits wide holds all
+// its 2^(bits + 1) entries at once. A wider one only holds its routes and
+// sub-tables, keyed by art_sparse_key(): routes are not alloted in it, and
+// the route covering an index is found by walking up from the index.
+enum ArtEntries<P, D> {
+    Dense(Vec<ArtEntry<P, D>>),
+    Sparse(BTreeMap<u64, ArtEntry<P, D>>),
+}
+
+impl<P, D> Clone for ArtEntries<P, D> {
+    fn clone(&self) -> Self {
+        match self {
+            ArtEntries::Dense(entry) => ArtEntries::Dense(entry.clone()),
+            ArtEntries::Sparse(held) => ArtEntries::Sparse(held.clone()),
+        }
+    }
+}
+
+// Entries of a table, in the order of ArtTable::next_held().
+enum ArtEntriesIterMut<'a, P, D> {
+    Dense(std::slice::IterMut<'a, ArtEntry<P, D>>),
+    Sparse(btree_map::ValuesMut<'a, u64, ArtEntry<P, D>>),
+}
+
+impl<'a, P, D> Iterator for ArtEntriesIterMut<'a, P, D> {
+    type Item = &'a mut ArtEntry<P, D>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            ArtEntriesIterMut::Dense(iter) => iter.next(),
+            ArtEntriesIterMut::Sparse(iter) => iter.next(),
+        }
+    }
+}
+
+impl<P, D> ArtTable<P, D> {
+    fn new(bits: &[u8], parent: Option<&ArtTable<P, D>>) -> Self {
+        let level = parent.map_or(0, |parent| parent.level + 1);
+        let stride = bits[level as usize];
+        let minfringe = 1u64 << stride;
+        let entry = if stride > ART_DENSE_STRIDE {
+            ArtEntries::Sparse(BTreeMap::new())
+        } else {
+            let mut entry = Vec::new();
+            entry.resize_with((minfringe << 1) as usize, || ArtEntry::None);
+            ArtEntries::Dense(entry)
+        };
+        ArtTable {
+            minfringe,
+            level,
+            bits: stride,
+            offset: parent.map_or(0, |parent| parent.offset + parent.bits),
+            refcnt: 0,
+            entry,
+        }
+    }
+
+    pub fn get_entry(&self, i: u64) -> &ArtEntry<P, D> {
+        match &self.entry {
+            ArtEntries::Dense(entry) => &entry[i as usize],
+            ArtEntries::Sparse(held) => held
+                .get(&art_sparse_key(self.bits, i))
+                .unwrap_or(&ArtEntry::None),
+        }
+    }
+
+    // Only called for an index holding something, as a sparse table would
+    // otherwise get an entry for nothing.
+    fn get_entry_mut(&mut self, i: u64) -> &mut ArtEntry<P, D> {
+        match &mut self.entry {
+            ArtEntries::Dense(entry) => &mut entry[i as usize],
+            ArtEntries::Sparse(held) => held
+                .entry(art_sparse_key(self.bits, i))
+                .or_insert(ArtEntry::None),
+        }
+    }
+
+    fn set_entry(&mut self, i: u64, an: ArtEntry<P, D>) {
+        match &mut self.entry {
+            ArtEntries::Dense(entry) => entry[i as usize] = an,
+            ArtEntries::Sparse(held) => {
+                let key = art_sparse_key(self.bits, i);
+                if let ArtEntry::None = an {
+                    held.remove(&key);
+                } else {
+                    held.insert(key, an);
+                }
+            }
+        }
+    }
+
+    fn is_sparse(&self) -> bool {
+        matches!(self.entry, ArtEntries::Sparse(_))
+    }
+
+    // The route held or alloted at index `i'.
+    fn get_route(&self, i: u64) -> &ArtEntry<P, D> {
+        match &self.entry {
+            ArtEntries::Dense(entry) => match &entry[i as usize] {
+                ArtEntry::Alloted(k) => &entry[*k as usize],
+                entry => entry,
+            },
+            ArtEntries::Sparse(_) => match self.get_entry(i) {
+                entry @ ArtEntry::Node(_) => entry,
+                _ => self.get_covering(i),
+            },
+        }
+    }
+
+    // The closest route above index `i' in a sparse table, the table
+    // default excepted as it is never alloted either.
+    fn get_covering(&self, mut i: u64) -> &ArtEntry<P, D> {
+        while i >> 1 > 1 {
+            i >>= 1;
+            if let entry @ ArtEntry::Node(_) = self.get_entry(i) {
+                return entry;
+            }
+        }
+        &ArtEntry::None
+    }
+
+    // The route of the sub-table `table' at the fringe index `j' of this
+    // table, if any. A route alloted to the fringe index is held by this
+    // table, and so is one covering it in a sparse table.
+    fn get_table_default<'a>(
+        &'a self,
+        j: u64,
+        table: &'a ArtTable<P, D>,
+    ) -> Option<&'a ArtEntry<P, D>> {
+        match table.get_default() {
+            ArtEntry::Alloted(k) => Some(self.get_entry(*k)),
+            ArtEntry::None if self.is_sparse() => match self.get_covering(j) {
+                ArtEntry::None => None,
+                route => Some(route),
+            },
+            ArtEntry::None => None,
+            entry => Some(entry),
+        }
+    }
+
+    fn get_default(&self) -> &ArtEntry<P, D> {
+        self.get_entry(1)
+    }
+
+    fn set_default(&mut self, an: ArtEntry<P, D>) {
+        self.set_entry(1, an);
+    }
+
+    // The first route or sub-table at position `pos' or after it, with the
+    // position that follows. Positions are the indices of a dense table and
+    // the keys of a sparse one, whose entries thus come in prefix order.
+    fn next_held(&self, pos: u64) -> Option<(u64, &ArtEntry<P, D>)> {
+        let held = |entry: &ArtEntry<P, D>| matches!(entry, ArtEntry::Node(_) | ArtEntry::Table(_));
+        match &self.entry {
+            ArtEntries::Dense(entry) => (pos as usize..entry.len())
+                .find(|&i| held(&entry[i]))
+                .map(|i| (i as u64 + 1, &entry[i])),
+            ArtEntries::Sparse(entries) => entries
+                .range(pos..)
+                .find(|(_, entry)| held(entry))
+                .map(|(key, entry)| (key + 1, entry)),
+        }
+    }
+
+    // Whether anything may be held below index `i'. Only a sparse table
+    // can tell without looking at every index.
+    fn holds_below(&self, i: u64) -> bool {
+        match &self.entry {
+            ArtEntries::Dense(_) => true,
+            ArtEntries::Sparse(held) => {
+                let (first, end) = art_sparse_range(self.bits, i);
+                held.range(first + 1..end).next().is_some()
+            }
+        }
+    }
+
+    // The indices holding something, or None for a dense table.
+    fn held_indices(&self) -> Option<Vec<u64>> {
+        match &self.entry {
+            ArtEntries::Dense(_) => None,
+            ArtEntries::Sparse(held) => Some(
+                held.keys()
+                    .map(|&key| art_sparse_index(self.bits, key))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn entries_mut(&mut self) -> ArtEntriesIterMut<'_, P, D> {
+        match &mut self.entry {
+            ArtEntries::Dense(entry) => ArtEntriesIterMut::Dense(entry.iter_mut()),
+            ArtEntries::Sparse(held) => ArtEntriesIterMut::Sparse(held.values_mut()),
+        }
+    }
+}
+
+// Copying a table only copies the pointers to its routes and sub-tables.
+impl<P, D> Clone for ArtTable<P, D> {
+    fn clone(&self) -> Self {
+        ArtTable {
+            minfringe: self.minfringe,
+            level: self.level,
+            bits: self.bits,
+            offset: self.offset,
+            refcnt: self.refcnt,
+            entry: self.entry.clone(),
+        }
+    }
+}
+
+pub struct ArtIter<P, D> {
+    stack: Vec<(Arc<ArtTable<P, D>>, u64)>,
+    at: Arc<ArtTable<P, D>>,
+    // Position in `at', see ArtTable::next_held().
+    pos: u64,
+}
+
+impl<P, D> IntoIterator for &ArtRoot<P, D>
+where
+    P: Prefix + Copy,
+{
+    type Item = Arc<ArtNode<P, D>>;
+    type IntoIter = ArtIter<P, D>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<P, D> Iterator for ArtIter<P, D>
+where
+    P: Prefix + Copy,
+{
+    type Item = Arc<ArtNode<P, D>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.at.next_held(self.pos) {
+                Some((pos, ArtEntry::Node(node))) => {
+                    let node = node.clone();
+                    self.pos = pos;
+                    return Some(node);
+                }
+                Some((pos, ArtEntry::Table(table))) => {
+                    let table = table.clone();
+                    let parent = std::mem::replace(&mut self.at, table);
+                    self.stack.push((parent, pos));
+                    self.pos = 0;
+                }
+                Some(_) => unreachable!(),
+                None => {
+                    (self.at, self.pos) = self.stack.pop()?;
+                }
+            }
+        }
+    }
+}
+
+impl<P, D> Default for ArtRoot<P, D>
+where
+    P: Prefix + Copy + DefaultLayout,
+{
+    fn default() -> Self {
+        ArtRoot::with_layout(&P::default_layout()).unwrap()
+    }
+}
+
+impl<P, D> FromIterator<(P, D)> for ArtRoot<P, D>
+where
+    P: Prefix + Copy + DefaultLayout,
+{
+    fn from_iter<I: IntoIterator<Item = (P, D)>>(iter: I) -> Self {
+        let mut root = Self::default();
+        root.extend(iter);
+        root
+    }
+}
+
+// A later route replaces an earlier one for the same prefix.
+impl<P, D> Extend<(P, D)> for ArtRoot<P, D>
+where
+    P: Prefix + Copy,
+{
+    fn extend<I: IntoIterator<Item = (P, D)>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().map(|(prefix, data)| (prefix, Some(data))));
+    }
+}
+
+// Routes without data, as yielded by into_routes(), are collected as well.
+impl<P, D> FromIterator<(P, Option<D>)> for ArtRoot<P, D>
+where
+    P: Prefix + Copy + DefaultLayout,
+{
+    fn from_iter<I: IntoIterator<Item = (P, Option<D>)>>(iter: I) -> Self {
+        let mut root = Self::default();
+        root.extend(iter);
+        root
+    }
+}
+
+impl<P, D> Extend<(P, Option<D>)> for ArtRoot<P, D>
+where
+    P: Prefix + Copy,
+{
+    fn extend<I: IntoIterator<Item = (P, Option<D>)>>(&mut self, iter: I) {
+        for (prefix, data) in iter {
+            self.insert(&ArtNode::new(&prefix, data), &prefix);
+        }
+    }
+}
+
+impl<P, D> IntoIterator for ArtRoot<P, D>
+where
+    P: Prefix + Copy,
+    D: Clone,
+{
+    type Item = (P, D);
+    type IntoIter = ArtIntoIter<P, D>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        ArtIntoIter {
+            all: self.into_routes(),
+        }
+    }
+}
+
+pub struct ArtIntoIter<P, D> {
+    all: ArtIntoRoutes<P, D>,
+}
+
+impl<P, D> Iterator for ArtIntoIter<P, D>
+where
+    P: Copy,
+    D: Clone,
+{
+    type Item = (P, D);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.all.find_map(|(prefix, data)| Some((prefix, data?)))
+    }
+}
+
+// Routes and tables still shared with another handle are copied, the
+// others are moved out.
+pub struct ArtIntoRoutes<P, D> {
+    stack: Vec<std::vec::IntoIter<ArtEntry<P, D>>>,
+}
+
+impl<P, D> Iterator for ArtIntoRoutes<P, D>
+where
+    P: Copy,
+    D: Clone,
+{
+    type Item = (P, Option<D>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.stack.last_mut()?.next() {
+                Some(ArtEntry::Table(table)) => {
+                    self.stack.push(art_into_entries(table).into_iter());
+                }
+                Some(ArtEntry::Node(node)) => {
+                    let ArtNode { prefix, data } =
+                        Arc::try_unwrap(node).unwrap_or_else(|node| (*node).clone());
+                    return Some((prefix, data));
+                }
+                Some(_) => {}
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
+
+pub struct ArtIterMut<'a, P, D> {
+    all: ArtIterMutAll<'a, P, D>,
+}
+
+impl<'a, P, D> IntoIterator for &'a mut ArtRoot<P, D>
+where
+    P: Prefix + Copy,
+    D: Clone,
+{
+    type Item = (P, ArtDataMut<'a, P, D>);
+    type IntoIter = ArtIterMut<'a, P, D>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<'a, P, D> Iterator for ArtIterMut<'a, P, D>
+where
+    P: Copy,
+    D: Clone,
+{
+    type Item = (P, ArtDataMut<'a, P, D>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.all.find_map(|(prefix, data)| Some((prefix, data?)))
+    }
+}
+
+pub struct ArtIterMutAll<'a, P, D> {
+    stack: Vec<ArtEntriesIterMut<'a, P, D>>,
+    observers: &'a ArtObservers<P, D>,
+}
+
+impl<'a, P, D> Iterator for ArtIterMutAll<'a, P, D>
+where
+    P: Copy,
+    D: Clone,
+{
+    type Item = (P, Option<ArtDataMut<'a, P, D>>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.stack.last_mut()?.next() {
+                Some(ArtEntry::Table(table)) => {
+                    self.stack.push(Arc::make_mut(table).entries_mut());
+                }
+                Some(ArtEntry::Node(node)) => {
+                    let prefix = node.prefix;
+                    let data = node.data.is_some();
+                    return Some((prefix, data.then(|| ArtDataMut::new(node, self.observers))));
+                }
+                Some(_) => {}
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
+
+// Pre-order walk of the base indices. Index `i' holds the route covering
+// those of `2i' and `2i + 1', which in turn cover the lower and the upper
+// half of its addresses, so a route comes right before its more specifics.
+// A sub-table continues the walk below its fringe index, its own index 1
+// being the fringe index itself.
+pub struct ArtSortedIter<P, D> {
+    stack: Vec<(Arc<ArtTable<P, D>>, u64)>,
+}
+
+impl<P, D> Iterator for ArtSortedIter<P, D>
+where
+    P: Prefix + Copy,
+{
+    type Item = Arc<ArtNode<P, D>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((at, i)) = self.stack.pop() {
+            self.push_children(&at, i);
+            let entry = match at.get_entry(i) {
+                ArtEntry::Table(table) => table.get_default(),
+                entry => entry,
+            };
+            if let ArtEntry::Node(node) = entry {
+                return Some(node.clone());
+            }
+        }
+        None
+    }
+}
+
+impl<P, D> ArtSortedIter<P, D> {
+    // Schedule the indices below `i', the lower half first.
+    fn push_children(&mut self, at: &Arc<ArtTable<P, D>>, i: u64) {
+        match at.get_entry(i) {
+            ArtEntry::Table(table) => {
+                self.stack.push((table.clone(), 3));
+                self.stack.push((table.clone(), 2));
+            }
+            _ if i < at.minfringe && at.holds_below(i) => {
+                self.stack.push((at.clone(), (i << 1) + 1));
+                self.stack.push((at.clone(), i << 1));
+            }
+            _ => {}
+        }
+    }
+}
+
+// Position in a table being compared, if the table goes that deep.
+type ArtDiffCursor<P, D> = Option<(Arc<ArtTable<P, D>>, u64)>;
+
+pub struct ArtDiff<P, D> {
+    // Pairs of positions still to visit, the same index of both tables.
+    stack: Vec<(ArtDiffCursor<P, D>, ArtDiffCursor<P, D>)>,
+    // Routes of both tables in prefix order when their layouts differ.
+    merge: Option<ArtDiffMerge<P, D>>,
+}
+
+struct ArtDiffMerge<P, D> {
+    old: ArtSortedIter<P, D>,
+    new: ArtSortedIter<P, D>,
+    // The next route of each, not compared yet.
+    old_next: Option<Arc<ArtNode<P, D>>>,
+    new_next: Option<Arc<ArtNode<P, D>>>,
+}
+
+impl<P, D> Iterator for ArtDiff<P, D>
+where
+    P: Prefix + Copy,
+    D: PartialEq,
+{
+    type Item = ArtChange<P, D>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(merge) = &mut self.merge {
+            return merge.next();
+        }
+
+        while let Some((old, new)) = self.stack.pop() {
+            let old_entry = old.as_ref().map(|(at, i)| at.get_entry(*i));
+            let new_entry = new.as_ref().map(|(at, i)| at.get_entry(*i));
+            if let (Some(ArtEntry::Table(a)), Some(ArtEntry::Table(b))) = (old_entry, new_entry) {
+                if Arc::ptr_eq(a, b) {
+                    continue;
+                }
+            }
+
+            // The lower half comes first, as in ArtSortedIter.
+            let (old_lower, old_upper) = art_diff_children(&old);
+            let (new_lower, new_upper) = art_diff_children(&new);
+            if old_upper.is_some() || new_upper.is_some() {
+                self.stack.push((old_upper, new_upper));
+            }
+            if old_lower.is_some() || new_lower.is_some() {
+                self.stack.push((old_lower, new_lower));
+            }
+
+            let old = old_entry.and_then(art_diff_route);
+            let new = new_entry.and_then(art_diff_route);
+            if let Some(change) = art_diff_change(old, new) {
+                return Some(change);
+            }
+        }
+        None
+    }
+}
+
+// The positions right below `cursor' in prefix order.
+fn art_diff_children<P, D>(
+    cursor: &ArtDiffCursor<P, D>,
+) -> (ArtDiffCursor<P, D>, ArtDiffCursor<P, D>) {
+    let Some((at, i)) = cursor else {
+        return (None, None);
+    };
+    match at.get_entry(*i) {
+        ArtEntry::Table(table) => (Some((table.clone(), 2)), Some((table.clone(), 3))),
+        _ if *i < at.minfringe && at.holds_below(*i) => {
+            (Some((at.clone(), i << 1)), Some((at.clone(), (i << 1) + 1)))
+        }
+        _ => (None, None),
+    }
+}
+
+// The route held at a position, which is the table default for a
+// sub-table.
+fn art_diff_route<P, D>(entry: &ArtEntry<P, D>) -> Option<Arc<ArtNode<P, D>>> {
+    match entry {
+        ArtEntry::Table(table) => table.get_default(),
+        entry => entry,
+    }
+    .node()
+}
+
+fn art_diff_change<P, D>(
+    old: Option<Arc<ArtNode<P, D>>>,
+    new: Option<Arc<ArtNode<P, D>>>,
+) -> Option<ArtChange<P, D>>
+where
+    D: PartialEq,
+{
+    match (old, new) {
+        (Some(old), Some(new)) => {
+            if Arc::ptr_eq(&old, &new) || old.data == new.data {
+                None
+            } else {
+                Some(ArtChange::Replace { old, new })
+            }
+        }
+        (Some(old), None) => Some(ArtChange::Delete(old)),
+        (None, Some(new)) => Some(ArtChange::Add(new)),
+        (None, None) => None,
+    }
+}
+
+impl<P, D> ArtDiffMerge<P, D>
+where
+    P: Prefix + Copy,
+    D: PartialEq,
+{
+    fn next(&mut self) -> Option<ArtChange<P, D>> {
+        loop {
+            if self.old_next.is_none() {
+                self.old_next = self.old.next();
+            }
+            if self.new_next.is_none() {
+                self.new_next = self.new.next();
+            }
+            let order = match (&self.old_next, &self.new_next) {
+                (Some(a), Some(b)) => art_prefix_cmp(&a.prefix, &b.prefix),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => return None,
+            };
+            let change = match order {
+                Ordering::Less => art_diff_change(self.old_next.take(), None),
+                Ordering::Greater => art_diff_change(None, self.new_next.take()),
+                Ordering::Equal => art_diff_change(self.old_next.take(), self.new_next.take()),
+            };
+            if change.is_some() {
+                return change;
+            }
+        }
+    }
+}
+
+// Prefix order: by address, then by prefix length.
+fn art_prefix_cmp<P: Prefix>(a: &P, b: &P) -> Ordering {
+    let (x, y) = (a.to_octets(), b.to_octets());
+    x.as_ref()
+        .cmp(y.as_ref())
+        .then(a.prefix_len().cmp(&b.prefix_len()))
+}
+
+#[derive(Clone)]
+pub struct ArtNode<P, D> {
+    pub prefix: P,
+    pub data: Option<D>,
+}
+
+impl<P, D> ArtNode<P, D>
+where
+    P: Prefix + Copy,
+{
+    pub fn new(prefix: &P, data: Option<D>) -> Arc<Self> {
+        Arc::new(Self {
+            prefix: *prefix,
+            data,
+        })
+    }
+}
+
+// A route is held only at its base index. The indices it covers record
+// that base index instead, so the route can be replaced or modified in one
+// place. At index 1 of a sub-table, `Alloted' refers to the parent table.
+pub enum ArtEntry<P, D> {
+    Table(Arc<ArtTable<P, D>>),
+    Node(Arc<ArtNode<P, D>>),
+    Alloted(u64),
+    None,
+}
+
+impl<P, D> Clone for ArtEntry<P, D> {
+    fn clone(&self) -> Self {
+        match self {
+            ArtEntry::Table(table) => ArtEntry::Table(table.clone()),
+            ArtEntry::Node(node) => ArtEntry::Node(node.clone()),
+            ArtEntry::Alloted(k) => ArtEntry::Alloted(*k),
+            ArtEntry::None => ArtEntry::None,
+        }
+    }
+}
+
+impl<P, D> ArtEntry<P, D> {
+    pub fn from_node(node: Arc<ArtNode<P, D>>) -> ArtEntry<P, D> {
+        ArtEntry::Node(node)
+    }
+
+    pub fn from_table(node: Arc<ArtTable<P, D>>) -> ArtEntry<P, D> {
+        ArtEntry::Table(node)
+    }
+
+    pub fn node(&self) -> Option<Arc<ArtNode<P, D>>> {
+        match self {
+            ArtEntry::Node(node) => Some(node.clone()),
+            _ => None,
+        }
+    }
+
+    // Entries are the same when they point to the same route or table.
+    fn is_same(a: &ArtEntry<P, D>, b: &ArtEntry<P, D>) -> bool {
+        match (a, b) {
+            (ArtEntry::Table(a), ArtEntry::Table(b)) => Arc::ptr_eq(a, b),
+            (ArtEntry::Node(a), ArtEntry::Node(b)) => Arc::ptr_eq(a, b),
+            (ArtEntry::Alloted(a), ArtEntry::Alloted(b)) => a == b,
+            (ArtEntry::None, ArtEntry::None) => true,
+            _ => false,
+        }
+    }
+}
+
+// Widest stride of a table, that of the widest range art_index() computes
+// an index for.
+pub const ART_MAX_STRIDE: u8 = ART_INDEX_MAX_BITS;
+
+// Widest table holding all its 2^(bits + 1) entries at once, which is
+// 512 MiB for the widest. Wider tables are sparse.
+pub const ART_DENSE_STRIDE: u8 = 24;
+
+// Check that ``levels'' strides of ``bits'' exactly cover an address of
+// ``alen'' bits.
+pub(crate) fn art_check_layout(levels: u32, bits: &[u8], alen: u8) -> Result<(), ArtError> {
+    if levels as usize != bits.len() {
+        return Err(ArtError::LevelMismatch {
+            levels,
+            strides: bits.len(),
+        });
+    }
+    for (level, &stride) in bits.iter().enumerate() {
+        if stride == 0 || stride > ART_MAX_STRIDE {
+            return Err(ArtError::InvalidStride {
+                level,
+                bits: stride,
+            });
+        }
+    }
+    let total: u32 = bits.iter().map(|&stride| stride as u32).sum();
+    if total != alen as u32 {
+        return Err(ArtError::LengthMismatch { total, alen });
+    }
+    Ok(())
+}
+
+// Check `alen' against the address length of `P'. A table built from a
+// layout covers whole addresses, others may look at only the first `alen'
+// bits of the addresses.
+pub(crate) fn art_check_alen<P: Prefix>(alen: u8, whole: bool) -> Result<(), ArtError> {
+    if alen > P::ALEN || (whole && alen != P::ALEN) {
+        return Err(ArtError::LengthMismatch {
+            total: alen as u32,
+            alen: P::ALEN,
+        });
+    }
+    Ok(())
+}
+
+// Return the base index of the part of ``addr'' and ``plen''
+// corresponding to the range covered by the table ``at''.
+//
+// In other words, this function take the multi-level (complete)
+// address ``addr'' and prefix length ``plen'' and return the
+// single level base index for the table ``at''.
+//
+// For example with an address size of 32bit divided into four
+// 8bit-long tables, there's a maximum of 4 base indexes if the
+// prefix length is > 24.
+//
+fn art_bindex<P, D>(at: &ArtTable<P, D>, addr: &[u8], plen: u8) -> Option<u64> {
+    art_index(at.offset, at.bits, addr, plen)
+}
+
+// Widest range art_index() can compute an index for: the bytes holding the
+// range, whatever its alignment, fit in the 64bit accumulator.
+pub(crate) const ART_INDEX_MAX_BITS: u8 = 57;
+
+// Same as art_bindex() for a table covering ``bits'' bits starting at
+// ``offset''.
+pub(crate) fn art_index(at_offset: u8, at_bits: u8, addr: &[u8], plen: u8) -> Option<u64> {
+    debug_assert!(at_bits <= ART_INDEX_MAX_BITS);
+
+    // The table may end at the last bit of the address.
+    let end = at_offset as usize + at_bits as usize;
+    if plen < at_offset || plen as usize > end {
+        return None;
     }
 
     // We are only interested in the part of the prefix length
     // corresponding to the range of this table.
-    plen -= at.offset;
+    let plen = plen - at_offset;
+
+    // Load the eight bytes of the address starting with the first bit
+    // covered by this table, zero padded past the end of the address, then
+    // drop the bits after and before its range.
+    let first = (at_offset / 8) as usize;
+    let mut octets = [0u8; 8];
+    match addr.get(first..first + 8) {
+        Some(bytes) => octets.copy_from_slice(bytes),
+        None => {
+            let bytes = &addr[first..end.div_ceil(8)];
+            octets[..bytes.len()].copy_from_slice(bytes);
+        }
+    }
+    let k = (u64::from_be_bytes(octets) >> (64 - (end - first * 8))) & ((1 << at_bits) - 1);
+
+    Some((k >> (at_bits - plen)) + (1 << plen))
+}
+
+fn art_findex<P, D>(at: &ArtTable<P, D>, addr: &[u8]) -> u64 {
+    art_bindex(at, addr, at.offset + at.bits).unwrap()
+}
+
+// Key of the index `i' in a sparse table of `bits' bits: the bits of the
+// index below its leading one, aligned on the left, then its depth. Keys
+// sort in prefix order, the indices below `i' right after it.
+fn art_sparse_key(bits: u8, i: u64) -> u64 {
+    let depth = 63 - i.leading_zeros() as u64;
+    ((i ^ (1 << depth)) << (bits as u64 - depth) << 6) | depth
+}
+
+fn art_sparse_index(bits: u8, key: u64) -> u64 {
+    let depth = key & 63;
+    (1 << depth) | (key >> 6 >> (bits as u64 - depth))
+}
 
-    // Jump to the first byte of the address containing bits
-    // covered by this table.
-    let addr = prefix.to_octets();
-    let offset: usize = (at.offset / 8) as usize;
+// Keys of the index `i' and of the indices below it, end excluded.
+fn art_sparse_range(bits: u8, i: u64) -> (u64, u64) {
+    let key = art_sparse_key(bits, i);
+    let depth = key & 63;
+    (key, ((key >> 6) + (1 << (bits as u64 - depth))) << 6)
+}
 
-    // ``at'' covers the bit range between ``boff'' & ``bend''. */
-    let boff = at.offset % 8;
-    let bend = at.bits + boff;
+// Walk `depth' tables down from `at' along the address.
+fn art_walk<'a, P, D>(mut at: &'a ArtTable<P, D>, addr: &[u8], depth: usize) -> &'a ArtTable<P, D> {
+    for _ in 0..depth {
+        at = match at.get_entry(art_findex(at, addr)) {
+            ArtEntry::Table(table) => table,
+            _ => unreachable!(),
+        };
+    }
+    at
+}
 
-    if bend > 24 {
-        k = ((addr[offset] as u32) & ((1 << (8 - boff)) - 1)) << (bend - 8);
-        k |= (addr[offset + 1] as u32) << (bend - 16);
-        k |= (addr[offset + 2] as u32) << (bend - 24);
-        k |= (addr[offset + 3] as u32) >> (32 - bend);
-    } else if bend > 16 {
-        k = ((addr[offset] as u32) & ((1 << (8 - boff)) - 1)) << (bend - 8);
-        k |= (addr[offset + 1] as u32) << (bend - 16);
-        k |= (addr[offset + 2] as u32) >> (24 - bend);
-    } else if bend > 8 {
-        k = ((addr[offset] as u32) & ((1 << (8 - boff)) - 1)) << (bend - 8);
-        k |= (addr[offset + 1] as u32) >> (16 - bend);
-    } else {
-        k = ((addr[offset] as u32) >> (8 - bend)) & ((1 << at.bits) - 1);
+fn art_walk_mut<'a, P, D>(
+    mut at: &'a mut ArtTable<P, D>,
+    addr: &[u8],
+    depth: usize,
+) -> &'a mut ArtTable<P, D> {
+    for _ in 0..depth {
+        at = match at.get_entry_mut(art_findex(at, addr)) {
+            ArtEntry::Table(table) => Arc::make_mut(table),
+            _ => unreachable!(),
+        };
     }
+    at
+}
 
-    Some((k >> (at.bits - plen)) + (1 << plen))
+// The entry holding the route of base index `i', looking through a
+// sub-table to its default.
+fn art_route_entry<P, D>(at: &ArtTable<P, D>, i: u64) -> &ArtEntry<P, D> {
+    match at.get_entry(i) {
+        ArtEntry::Table(table) => table.get_default(),
+        entry => entry,
+    }
 }
 
-fn art_findex<P, D>(at: &ArtTable<P, D>, prefix: &P) -> Option<u32>
+// The slot of the route for the prefix held by `at'. A sub-table holding
+// it as its default is copied first if it is shared.
+fn art_route_mut<'a, P, D>(
+    at: &'a mut ArtTable<P, D>,
+    addr: &[u8],
+    plen: u8,
+) -> &'a mut Arc<ArtNode<P, D>>
 where
-    P: Prefix + Copy,
+    P: Clone,
+    D: Clone,
 {
-    art_bindex(at, prefix, at.offset + at.bits)
+    let i = art_bindex(at, addr, plen).unwrap();
+    let entry = match at.get_entry_mut(i) {
+        ArtEntry::Table(table) => Arc::make_mut(table).get_entry_mut(1),
+        entry => entry,
+    };
+    match entry {
+        ArtEntry::Node(node) => node,
+        _ => unreachable!(),
+    }
+}
+
+// What an index below `k' holds when it has no route of its own. Index 1
+// is the table default and is not alloted, and neither is anything in a
+// sparse table.
+fn art_covering<P, D>(at: &ArtTable<P, D>, k: u64) -> ArtEntry<P, D> {
+    if k <= 1 || at.is_sparse() {
+        return ArtEntry::None;
+    }
+    match at.get_entry(k) {
+        ArtEntry::Node(_) => ArtEntry::Alloted(k),
+        entry => entry.clone(),
+    }
 }
 
-fn art_allot<P, D>(at: &ArtTable<P, D>, i: u32, old: Rc<ArtEntry<P, D>>, new: &Rc<ArtEntry<P, D>>) {
+// The entries of the table, moved out unless it is still shared.
+fn art_into_entries<P, D>(table: Arc<ArtTable<P, D>>) -> Vec<ArtEntry<P, D>> {
+    let entry = Arc::try_unwrap(table)
+        .map(|table| table.entry)
+        .unwrap_or_else(|table| table.entry.clone());
+    match entry {
+        ArtEntries::Dense(entry) => entry,
+        ArtEntries::Sparse(held) => held.into_values().collect(),
+    }
+}
+
+fn art_allot<P, D>(at: &mut ArtTable<P, D>, i: u64, old: &ArtEntry<P, D>, new: &ArtEntry<P, D>) {
+    if at.is_sparse() {
+        return;
+    }
     let mut k = i;
 
-    let exist = at.get_entry(k);
-    match exist.as_ref() {
+    match at.get_entry_mut(k) {
         ArtEntry::Table(table) => {
-            let default = table.get_default();
-            if std::ptr::eq(default.as_ref(), old.as_ref()) {
-                table.set_default(new.clone());
+            if ArtEntry::is_same(table.get_default(), old) {
+                Arc::make_mut(table).set_default(new.clone());
             }
+            return;
         }
-        ArtEntry::Node(_) => {
-            if std::ptr::eq(exist.as_ref(), old.as_ref()) {
-                at.set_entry(k, new.clone());
+        exist => {
+            // A more specific route owns this index and everything below it.
+            if !ArtEntry::is_same(exist, old) {
+                return;
             }
-        }
-        ArtEntry::None => {
-            at.set_entry(k, new.clone());
+            *exist = new.clone();
         }
     }
 
@@ -554,9 +1979,9 @@ fn art_allot<P, D>(at: &ArtTable<P, D>, i: u32, old: Rc<ArtEntry<P, D>>, new: &R
     }
 
     k <<= 1;
-    art_allot(at, k, old.clone(), &new.clone());
+    art_allot(at, k, old, new);
     k += 1;
-    art_allot(at, k, old.clone(), &new.clone());
+    art_allot(at, k, old, new);
 }
 
 #[cfg(test)]
@@ -565,31 +1990,241 @@ mod tests {
 
     #[test]
     pub fn test_art_bindex() {
-        let ar = ArtRoot::<Ipv4Net, u32>::new(8, [4u8; 8].to_vec(), 32);
-        let at = ArtTable::new(&ar, None, 0);
+        let ar = ArtRoot::<Ipv4Net, u32>::new(8, [4u8; 8].to_vec(), 32).unwrap();
+        let at = ArtTable::<Ipv4Net, u32>::new(&ar.bits, None);
 
         let net0: Ipv4Net = "0.0.0.0/0".parse().unwrap();
-        let bindex = art_bindex(&at, &net0, net0.prefix_len()).unwrap();
+        let bindex = art_bindex(&at, &net0.to_octets(), net0.prefix_len()).unwrap();
         assert_eq!(bindex, 1);
 
         let net0: Ipv4Net = "0.0.0.0/1".parse().unwrap();
-        let bindex = art_bindex(&at, &net0, net0.prefix_len()).unwrap();
+        let bindex = art_bindex(&at, &net0.to_octets(), net0.prefix_len()).unwrap();
         assert_eq!(bindex, 2);
 
         let net128: Ipv4Net = "128.0.0.0/1".parse().unwrap();
-        let bindex = art_bindex(&at, &net128, net128.prefix_len()).unwrap();
+        let bindex = art_bindex(&at, &net128.to_octets(), net128.prefix_len()).unwrap();
         assert_eq!(bindex, 3);
 
         let net128: Ipv4Net = "128.0.0.0/4".parse().unwrap();
-        let bindex = art_bindex(&at, &net128, net128.prefix_len()).unwrap();
+        let bindex = art_bindex(&at, &net128.to_octets(), net128.prefix_len()).unwrap();
         assert_eq!(bindex, 24);
 
         let net224: Ipv4Net = "224.0.0.0/3".parse().unwrap();
-        let bindex = art_bindex(&at, &net224, net224.prefix_len()).unwrap();
+        let bindex = art_bindex(&at, &net224.to_octets(), net224.prefix_len()).unwrap();
         assert_eq!(bindex, 15);
 
         let net240: Ipv4Net = "240.0.0.0/4".parse().unwrap();
-        let bindex = art_bindex(&at, &net240, net240.prefix_len()).unwrap();
+        let bindex = art_bindex(&at, &net240.to_octets(), net240.prefix_len()).unwrap();
+        assert_eq!(bindex, 31);
+    }
+
+    #[test]
+    pub fn test_art_bindex_ipv6() {
+        let ar = ArtRoot::<Ipv6Net, u32>::new_ipv6_table();
+        let root = ar.root();
+
+        let net: Ipv6Net = "::/0".parse().unwrap();
+        let bindex = art_bindex(&root, &net.to_octets(), net.prefix_len()).unwrap();
+        assert_eq!(bindex, 1);
+
+        let net: Ipv6Net = "2000::/3".parse().unwrap();
+        let bindex = art_bindex(&root, &net.to_octets(), net.prefix_len()).unwrap();
+        assert_eq!(bindex, 9);
+
+        // Table covering bits 120..124 of the address.
+        let mut at = ArtTable::new(&ar.bits, Some(&root));
+        while at.offset < 120 {
+            at = ArtTable::new(&ar.bits, Some(&at));
+        }
+        let net: Ipv6Net = "2001:db8::f0/124".parse().unwrap();
+        let bindex = art_bindex(&at, &net.to_octets(), net.prefix_len()).unwrap();
         assert_eq!(bindex, 31);
+
+        let net: Ipv6Net = "2001:db8::80/121".parse().unwrap();
+        let bindex = art_bindex(&at, &net.to_octets(), net.prefix_len()).unwrap();
+        assert_eq!(bindex, 3);
+    }
+
+    // Index computed one address bit at a time.
+    fn art_index_bitwise(offset: u8, addr: &[u8], plen: u8) -> u64 {
+        let mut k: u64 = 1;
+        for bit in offset..plen {
+            let byte = addr[(bit / 8) as usize];
+            k = (k << 1) | ((byte >> (7 - bit % 8)) & 1) as u64;
+        }
+        k
+    }
+
+    #[test]
+    pub fn test_art_index_alignment() {
+        let addrs: [[u8; 16]; 4] = [
+            [0xff; 16],
+            [0xa5; 16],
+            [
+                0x20, 0x01, 0x0d, 0xb8, 0x80, 0x01, 0x7f, 0xfe, 0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc,
+                0xde, 0xf0,
+            ],
+            [
+                0x0f, 0x1e, 0x2d, 0x3c, 0x4b, 0x5a, 0x69, 0x78, 0x87, 0x96, 0xa5, 0xb4, 0xc3, 0xd2,
+                0xe1, 0xf0,
+            ],
+        ];
+
+        // Every range of every width, starting at every bit of the address,
+        // both for addresses of exactly the address length and for longer
+        // ones.
+        for alen in [32u8, 128u8] {
+            for addr in addrs.iter() {
+                let short = &addr[..(alen / 8) as usize];
+                for offset in 0..alen {
+                    for bits in 1..=ART_INDEX_MAX_BITS.min(alen - offset) {
+                        for plen in offset..=offset + bits {
+                            let k = art_index_bitwise(offset, short, plen);
+                            assert_eq!(art_index(offset, bits, short, plen), Some(k));
+                            assert_eq!(art_index(offset, bits, addr, plen), Some(k));
+                        }
+                        assert_eq!(art_index(offset, bits, short, offset + bits + 1), None);
+                        if offset > 0 {
+                            assert_eq!(art_index(offset, bits, short, offset - 1), None);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_art_index_flat() {
+        // Indices of a single level spanning the whole IPv4 address.
+        for addr in [0u32, 1, 0x0a000001, 0x80000000, 0xfffffffe, u32::MAX] {
+            let octets = addr.to_be_bytes();
+            assert_eq!(art_index(0, 32, &octets, 0), Some(1));
+            assert_eq!(art_index(0, 32, &octets, 1), Some(2 + (addr >> 31) as u64));
+            assert_eq!(
+                art_index(0, 32, &octets, 24),
+                Some((1 << 24) + (addr >> 8) as u64)
+            );
+            assert_eq!(art_index(0, 32, &octets, 32), Some((1 << 32) + addr as u64));
+        }
+
+        // The widest table stride, starting on the last bit of a byte.
+        let octets = [0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+        let k = art_index(7, ART_MAX_STRIDE, &octets, 7 + ART_MAX_STRIDE).unwrap();
+        assert_eq!(k, (1 << (ART_MAX_STRIDE + 1)) - 1);
+    }
+
+    #[test]
+    pub fn test_art_sparse_key() {
+        // Keys of a sparse table follow the pre-order of its indices.
+        for bits in [4, 25, ART_MAX_STRIDE] {
+            let indices = [1, 2, 4, 9, 18, 5, 3, 6, 13, 7];
+            let keys: Vec<u64> = indices.iter().map(|&i| art_sparse_key(bits, i)).collect();
+            assert!(keys.windows(2).all(|w| w[0] < w[1]));
+            for (&i, &key) in indices.iter().zip(keys.iter()) {
+                assert_eq!(art_sparse_index(bits, key), i);
+            }
+
+            let (start, end) = art_sparse_range(bits, 2);
+            assert!(keys[1..6].iter().all(|key| (start..end).contains(key)));
+            assert!(!keys[6..].iter().any(|key| (start..end).contains(key)));
+            let widest = (1 << (bits + 1)) - 1;
+            assert!(art_sparse_key(bits, widest) < art_sparse_range(bits, 1).1);
+        }
+    }
+
+    fn has_table<P, D>(at: &ArtTable<P, D>) -> bool {
+        (1..(at.minfringe << 1)).any(|i| matches!(at.get_entry(i), ArtEntry::Table(_)))
+    }
+
+    #[test]
+    pub fn test_art_table_free() {
+        let mut ar = ArtRoot::<Ipv4Net, u32>::new_ipv4_table();
+        let routes = ["10.0.0.0/8", "10.0.0.0/16", "10.0.0.0/24", "10.0.0.0/32"];
+
+        for (i, route) in routes.iter().enumerate() {
+            ar.route_ipv4_add(route.parse().unwrap(), i as u32);
+        }
+        assert!(has_table(&ar.root()));
+
+        // The /8 stays in the root table, the /32 keeps the chain alive.
+        ar.route_ipv4_delete("10.0.0.0/16".parse().unwrap());
+        ar.route_ipv4_delete("10.0.0.0/24".parse().unwrap());
+        assert!(has_table(&ar.root()));
+        assert_eq!(ar.root().refcnt, 2);
+
+        ar.route_ipv4_delete("10.0.0.0/32".parse().unwrap());
+        assert!(!has_table(&ar.root()));
+        assert_eq!(ar.root().refcnt, 1);
+
+        // The fringe index of the freed table holds the /8 again.
+        let node = ar.lookup(&"10.0.0.1/32".parse().unwrap()).unwrap();
+        assert_eq!(node.prefix, "10.0.0.0/8".parse::<Ipv4Net>().unwrap());
+
+        ar.route_ipv4_delete("10.0.0.0/8".parse().unwrap());
+        assert_eq!(ar.root().refcnt, 0);
+        assert!(ar.lookup(&"10.0.0.1/32".parse().unwrap()).is_none());
+    }
+
+    #[test]
+    pub fn test_art_table_free_alloted() {
+        let mut ar = ArtRoot::<Ipv4Net, u32>::new_ipv4_table();
+
+        ar.route_ipv4_add("10.0.0.0/7".parse().unwrap(), 7);
+        ar.route_ipv4_add("10.0.0.0/8".parse().unwrap(), 8);
+        ar.route_ipv4_add("10.1.0.0/16".parse().unwrap(), 16);
+
+        // Deleting a route that is not in the table leaves the alloted /8
+        // in place.
+        ar.route_ipv4_delete("10.0.0.0/9".parse().unwrap());
+        ar.route_ipv4_delete("10.2.0.0/16".parse().unwrap());
+        assert_eq!(ar.iter().count(), 3);
+
+        // The fringe index and the sub-table default fall back to the /7.
+        ar.route_ipv4_delete("10.0.0.0/8".parse().unwrap());
+        let node = ar.lookup(&"10.1.0.1/32".parse().unwrap()).unwrap();
+        assert_eq!(node.prefix, "10.1.0.0/16".parse::<Ipv4Net>().unwrap());
+        let node = ar.lookup(&"10.2.0.1/32".parse().unwrap()).unwrap();
+        assert_eq!(node.prefix, "10.0.0.0/7".parse::<Ipv4Net>().unwrap());
+
+        ar.route_ipv4_delete("10.1.0.0/16".parse().unwrap());
+        assert!(!has_table(&ar.root()));
+        let node = ar.lookup(&"10.1.0.1/32".parse().unwrap()).unwrap();
+        assert_eq!(node.prefix, "10.0.0.0/7".parse::<Ipv4Net>().unwrap());
+        assert_eq!(ar.iter().count(), 1);
+    }
+
+    #[test]
+    pub fn test_art_table_drain() {
+        let mut ar = ArtRoot::<Ipv4Net, u32>::new_ipv4_table();
+        let routes = ["10.0.0.0/8", "10.0.0.0/16", "10.0.0.0/24", "10.0.0.0/32"];
+
+        for (i, route) in routes.iter().enumerate() {
+            ar.route_ipv4_add(route.parse().unwrap(), i as u32);
+        }
+        let shared = ar.share();
+
+        // The chain of tables is freed, the shared copy is left untouched.
+        let removed = ar.drain_filter(|prefix, _| prefix.prefix_len() > 8);
+        assert_eq!(removed.len(), 3);
+        assert!(!has_table(&ar.root()));
+        assert_eq!(ar.root().refcnt, 1);
+        let node = ar.lookup(&"10.0.0.1/32".parse().unwrap()).unwrap();
+        assert_eq!(node.prefix, "10.0.0.0/8".parse::<Ipv4Net>().unwrap());
+
+        assert!(has_table(&shared.root()));
+        assert_eq!(shared.iter().count(), 4);
+        let node = shared.lookup(&"10.0.0.0/32".parse().unwrap()).unwrap();
+        assert_eq!(node.prefix, "10.0.0.0/32".parse::<Ipv4Net>().unwrap());
+
+        // A fringe route held as a sub-table default is accounted for in
+        // the parent table.
+        ar.route_ipv4_add("10.0.0.0/16".parse().unwrap(), 1);
+        ar.retain(|prefix, _| prefix.prefix_len() != 8);
+        assert!(has_table(&ar.root()));
+        assert_eq!(ar.root().refcnt, 1);
+        ar.retain(|_, _| false);
+        assert!(!has_table(&ar.root()));
+        assert_eq!(ar.root().refcnt, 0);
+        assert!(ar.is_empty());
     }
 }
@@ -0,0 +1,269 @@
+use crate::art::{ArtIntoIter, ArtIntoRoutes, ArtIter, ArtNode, ArtRoot};
+use crate::error::ArtError;
+use crate::layout::ArtLayout;
+use ipnet::{IpNet, Ipv4Net, Ipv6Net};
+use std::net::IpAddr;
+use std::sync::Arc;
+
+// Dual-stack routing table. IPv4 and IPv6 prefixes are kept in two separate
+// ART tables, each with the stride layout of its address family, and every
+// operation is dispatched on the address family of the given prefix. The
+// tables are keyed by `Ipv4Net' and `Ipv6Net', so a prefix can only ever
+// reach the table of its own family.
+pub struct ArtDualRoot<D> {
+    ipv4: ArtRoot<Ipv4Net, D>,
+    ipv6: ArtRoot<Ipv6Net, D>,
+}
+
+// A route of either table.
+pub enum ArtDualNode<D> {
+    V4(Arc<ArtNode<Ipv4Net, D>>),
+    V6(Arc<ArtNode<Ipv6Net, D>>),
+}
+
+impl<D> ArtDualNode<D> {
+    pub fn new(prefix: &IpNet, data: Option<D>) -> Self {
+        match prefix {
+            IpNet::V4(prefix) => ArtDualNode::V4(ArtNode::new(prefix, data)),
+            IpNet::V6(prefix) => ArtDualNode::V6(ArtNode::new(prefix, data)),
+        }
+    }
+
+    pub fn prefix(&self) -> IpNet {
+        match self {
+            ArtDualNode::V4(node) => node.prefix.into(),
+            ArtDualNode::V6(node) => node.prefix.into(),
+        }
+    }
+
+    pub fn data(&self) -> Option<&D> {
+        match self {
+            ArtDualNode::V4(node) => node.data.as_ref(),
+            ArtDualNode::V6(node) => node.data.as_ref(),
+        }
+    }
+}
+
+impl<D> Clone for ArtDualNode<D> {
+    fn clone(&self) -> Self {
+        match self {
+            ArtDualNode::V4(node) => ArtDualNode::V4(node.clone()),
+            ArtDualNode::V6(node) => ArtDualNode::V6(node.clone()),
+        }
+    }
+}
+
+impl<D> ArtDualRoot<D> {
+    pub fn new() -> Self {
+        ArtDualRoot {
+            ipv4: ArtRoot::new_ipv4_table(),
+            ipv6: ArtRoot::new_ipv6_table(),
+        }
+    }
+
+    pub fn with_layouts(ipv4: &ArtLayout, ipv6: &ArtLayout) -> Result<Self, ArtError> {
+        Ok(ArtDualRoot {
+            ipv4: ArtRoot::with_layout(ipv4)?,
+            ipv6: ArtRoot::with_layout(ipv6)?,
+        })
+    }
+
+    pub fn ipv4(&self) -> &ArtRoot<Ipv4Net, D> {
+        &self.ipv4
+    }
+
+    pub fn ipv6(&self) -> &ArtRoot<Ipv6Net, D> {
+        &self.ipv6
+    }
+
+    pub fn len(&self) -> usize {
+        self.ipv4.len() + self.ipv6.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ipv4.is_empty() && self.ipv6.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.ipv4.clear();
+        self.ipv6.clear();
+    }
+
+    pub fn insert(&mut self, an: &ArtDualNode<D>) -> Option<ArtDualNode<D>> {
+        match an {
+            ArtDualNode::V4(an) => self.ipv4.insert(an, &an.prefix).map(ArtDualNode::V4),
+            ArtDualNode::V6(an) => self.ipv6.insert(an, &an.prefix).map(ArtDualNode::V6),
+        }
+    }
+
+    pub fn lookup(&self, prefix: &IpNet) -> Option<ArtDualNode<D>> {
+        match prefix {
+            IpNet::V4(prefix) => self.ipv4.lookup(prefix).map(ArtDualNode::V4),
+            IpNet::V6(prefix) => self.ipv6.lookup(prefix).map(ArtDualNode::V6),
+        }
+    }
+
+    pub fn lookup_addr(&self, addr: IpAddr) -> Option<ArtDualNode<D>> {
+        match addr {
+            IpAddr::V4(addr) => self.ipv4.lookup_addr(addr).map(ArtDualNode::V4),
+            IpAddr::V6(addr) => self.ipv6.lookup_addr(addr).map(ArtDualNode::V6),
+        }
+    }
+
+    pub fn lookup_exact(&self, prefix: &IpNet) -> Option<ArtDualNode<D>> {
+        match prefix {
+            IpNet::V4(prefix) => self.ipv4.lookup_exact(prefix).map(ArtDualNode::V4),
+            IpNet::V6(prefix) => self.ipv6.lookup_exact(prefix).map(ArtDualNode::V6),
+        }
+    }
+
+    pub fn delete(&mut self, prefix: &IpNet) -> Option<ArtDualNode<D>> {
+        match prefix {
+            IpNet::V4(prefix) => self.ipv4.delete(prefix).map(ArtDualNode::V4),
+            IpNet::V6(prefix) => self.ipv6.delete(prefix).map(ArtDualNode::V6),
+        }
+    }
+
+    // Iterate IPv4 routes first, then IPv6 routes.
+    pub fn iter(&self) -> ArtDualIter<D> {
+        ArtDualIter {
+            ipv4: self.ipv4.iter(),
+            ipv6: self.ipv6.iter(),
+        }
+    }
+
+    pub fn route_add(&mut self, prefix: IpNet, data: D) -> Option<ArtDualNode<D>> {
+        match prefix {
+            IpNet::V4(prefix) => self.ipv4.route_ipv4_add(prefix, data).map(ArtDualNode::V4),
+            IpNet::V6(prefix) => self.ipv6.route_ipv6_add(prefix, data).map(ArtDualNode::V6),
+        }
+    }
+
+    pub fn route_delete(&mut self, prefix: IpNet) -> Option<ArtDualNode<D>> {
+        self.delete(&prefix)
+    }
+
+    pub fn route_lookup(&self, prefix: IpNet) -> Option<ArtDualNode<D>> {
+        self.lookup(&prefix)
+    }
+
+    pub fn route_lookup_exact(&self, prefix: IpNet) -> Option<ArtDualNode<D>> {
+        self.lookup_exact(&prefix)
+    }
+}
+
+impl<D: Clone> ArtDualRoot<D> {
+    // Like into_iter(), with the routes without data yielded with None.
+    pub fn into_routes(self) -> ArtDualIntoRoutes<D> {
+        ArtDualIntoRoutes {
+            ipv4: self.ipv4.into_routes(),
+            ipv6: self.ipv6.into_routes(),
+        }
+    }
+}
+
+impl<D> Default for ArtDualRoot<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D> IntoIterator for &ArtDualRoot<D> {
+    type Item = ArtDualNode<D>;
+    type IntoIter = ArtDualIter<D>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<D> FromIterator<(IpNet, D)> for ArtDualRoot<D> {
+    fn from_iter<I: IntoIterator<Item = (IpNet, D)>>(iter: I) -> Self {
+        let mut root = Self::new();
+        root.extend(iter);
+        root
+    }
+}
+
+impl<D> Extend<(IpNet, D)> for ArtDualRoot<D> {
+    fn extend<I: IntoIterator<Item = (IpNet, D)>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().map(|(prefix, data)| (prefix, Some(data))));
+    }
+}
+
+impl<D> FromIterator<(IpNet, Option<D>)> for ArtDualRoot<D> {
+    fn from_iter<I: IntoIterator<Item = (IpNet, Option<D>)>>(iter: I) -> Self {
+        let mut root = Self::new();
+        root.extend(iter);
+        root
+    }
+}
+
+impl<D> Extend<(IpNet, Option<D>)> for ArtDualRoot<D> {
+    fn extend<I: IntoIterator<Item = (IpNet, Option<D>)>>(&mut self, iter: I) {
+        for (prefix, data) in iter {
+            self.insert(&ArtDualNode::new(&prefix, data));
+        }
+    }
+}
+
+impl<D: Clone> IntoIterator for ArtDualRoot<D> {
+    type Item = (IpNet, D);
+    type IntoIter = ArtDualIntoIter<D>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        ArtDualIntoIter {
+            ipv4: self.ipv4.into_iter(),
+            ipv6: self.ipv6.into_iter(),
+        }
+    }
+}
+
+// The iterators yield IPv4 routes first, then IPv6 routes.
+pub struct ArtDualIter<D> {
+    ipv4: ArtIter<Ipv4Net, D>,
+    ipv6: ArtIter<Ipv6Net, D>,
+}
+
+impl<D> Iterator for ArtDualIter<D> {
+    type Item = ArtDualNode<D>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.ipv4.next() {
+            Some(node) => Some(ArtDualNode::V4(node)),
+            None => self.ipv6.next().map(ArtDualNode::V6),
+        }
+    }
+}
+
+pub struct ArtDualIntoIter<D> {
+    ipv4: ArtIntoIter<Ipv4Net, D>,
+    ipv6: ArtIntoIter<Ipv6Net, D>,
+}
+
+impl<D: Clone> Iterator for ArtDualIntoIter<D> {
+    type Item = (IpNet, D);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.ipv4.next() {
+            Some((prefix, data)) => Some((prefix.into(), data)),
+            None => self.ipv6.next().map(|(prefix, data)| (prefix.into(), data)),
+        }
+    }
+}
+
+pub struct ArtDualIntoRoutes<D> {
+    ipv4: ArtIntoRoutes<Ipv4Net, D>,
+    ipv6: ArtIntoRoutes<Ipv6Net, D>,
+}
+
+impl<D: Clone> Iterator for ArtDualIntoRoutes<D> {
+    type Item = (IpNet, Option<D>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.ipv4.next() {
+            Some((prefix, data)) => Some((prefix.into(), data)),
+            None => self.ipv6.next().map(|(prefix, data)| (prefix.into(), data)),
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use std::fmt;
+
+// Errors returned for an unusable table layout or prefix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArtError {
+    // The number of levels is not the number of strides given.
+    LevelMismatch { levels: u32, strides: usize },
+    // A stride is zero or wider than ART_MAX_STRIDE bits, or than
+    // ART_DENSE_STRIDE bits in an arena.
+    InvalidStride { level: usize, bits: u8 },
+    // The strides do not add up to the address length, or the address
+    // length does not fit the addresses of the prefixes.
+    LengthMismatch { total: u32, alen: u8 },
+    // The prefix is longer than the address length of the table.
+    PrefixTooLong { plen: u8, alen: u8 },
+}
+
+impl fmt::Display for ArtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArtError::LevelMismatch { levels, strides } => {
+                write!(f, "{} levels given for {} strides", levels, strides)
+            }
+            ArtError::InvalidStride { level, bits } => {
+                write!(f, "invalid stride of {} bits at level {}", bits, level)
+            }
+            ArtError::LengthMismatch { total, alen } => {
+                write!(f, "strides cover {} bits of a {} bit address", total, alen)
+            }
+            ArtError::PrefixTooLong { plen, alen } => {
+                write!(f, "prefix length {} exceeds address length {}", plen, alen)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ArtError {}
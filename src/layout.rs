@@ -0,0 +1,122 @@
+use crate::art::art_check_layout;
+use crate::error::ArtError;
+use ipnet::{Ipv4Net, Ipv6Net};
+
+// Stride layout of a table: the number of address bits indexed at each
+// level, from the root table down. Wide strides make for fewer levels and
+// faster lookups, narrow strides for smaller tables.
+//
+// A layout is checked when it is built, so a table can be created from it
+// without error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArtLayout {
+    bits: Vec<u8>,
+    alen: u8,
+}
+
+impl ArtLayout {
+    pub fn new(bits: Vec<u8>, alen: u8) -> Result<Self, ArtError> {
+        art_check_layout(bits.len() as u32, &bits, alen)?;
+        Ok(ArtLayout { bits, alen })
+    }
+
+    pub fn builder(alen: u8) -> ArtLayoutBuilder {
+        ArtLayoutBuilder {
+            bits: Vec::new(),
+            alen,
+        }
+    }
+
+    pub fn levels(&self) -> u32 {
+        self.bits.len() as u32
+    }
+
+    pub fn bits(&self) -> &[u8] {
+        &self.bits
+    }
+
+    pub fn alen(&self) -> u8 {
+        self.alen
+    }
+
+    // The layout of the BSD routing table, also the default for IPv4.
+    pub fn ipv4_8_4_4_4_4_4_4() -> Self {
+        ArtLayout::new([8, 4, 4, 4, 4, 4, 4].to_vec(), 32).unwrap()
+    }
+
+    pub fn ipv4_8_8_8_8() -> Self {
+        ArtLayout::new([8, 8, 8, 8].to_vec(), 32).unwrap()
+    }
+
+    // Most IPv4 routes are /24 or shorter and found in the first two
+    // levels.
+    pub fn ipv4_16_8_8() -> Self {
+        ArtLayout::new([16, 8, 8].to_vec(), 32).unwrap()
+    }
+
+    // The root table alone has 2^25 entries.
+    pub fn ipv4_24_8() -> Self {
+        ArtLayout::new([24, 8].to_vec(), 32).unwrap()
+    }
+
+    // The default for IPv6.
+    pub fn ipv6_4x32() -> Self {
+        ArtLayout::new([4; 32].to_vec(), 128).unwrap()
+    }
+
+    pub fn ipv6_8x16() -> Self {
+        ArtLayout::new([8; 16].to_vec(), 128).unwrap()
+    }
+
+    // Most IPv6 routes are /48 or shorter and found in the first four
+    // levels.
+    pub fn ipv6_16_16_8x12() -> Self {
+        ArtLayout::builder(128)
+            .stride(16)
+            .stride(16)
+            .strides(8, 12)
+            .build()
+            .unwrap()
+    }
+}
+
+// Build a layout one level at a time, from the root table down.
+pub struct ArtLayoutBuilder {
+    bits: Vec<u8>,
+    alen: u8,
+}
+
+impl ArtLayoutBuilder {
+    pub fn stride(mut self, bits: u8) -> Self {
+        self.bits.push(bits);
+        self
+    }
+
+    // Add `count' levels of the same stride.
+    pub fn strides(mut self, bits: u8, count: usize) -> Self {
+        self.bits.extend(std::iter::repeat_n(bits, count));
+        self
+    }
+
+    pub fn build(self) -> Result<ArtLayout, ArtError> {
+        ArtLayout::new(self.bits, self.alen)
+    }
+}
+
+// Stride layout of the tables built without one being given, as by
+// ArtRoot::default() or collect().
+pub trait DefaultLayout {
+    fn default_layout() -> ArtLayout;
+}
+
+impl DefaultLayout for Ipv4Net {
+    fn default_layout() -> ArtLayout {
+        ArtLayout::ipv4_8_4_4_4_4_4_4()
+    }
+}
+
+impl DefaultLayout for Ipv6Net {
+    fn default_layout() -> ArtLayout {
+        ArtLayout::ipv6_4x32()
+    }
+}
@@ -1,3 +1,24 @@
-pub use self::art::{ArtEntry, ArtNode, ArtRoot, ArtTable};
+pub use self::arena::{ArtArenaIter, ArtArenaRoot};
+pub use self::art::{
+    ArtDiff, ArtEntry, ArtIntoIter, ArtIntoRoutes, ArtIter, ArtIterMut, ArtIterMutAll, ArtNode,
+    ArtOccupiedEntry, ArtRoot, ArtRouteEntry, ArtSortedIter, ArtTable, ArtVacantEntry, Prefix,
+    ART_DENSE_STRIDE, ART_MAX_STRIDE,
+};
+pub use self::dual::{ArtDualIntoIter, ArtDualIntoRoutes, ArtDualIter, ArtDualNode, ArtDualRoot};
+pub use self::error::ArtError;
+pub use self::layout::{ArtLayout, ArtLayoutBuilder, DefaultLayout};
+pub use self::observer::{ArtChange, ArtDataMut};
+pub use self::rcu::{ArtRcuRoot, ArtRcuTransaction};
+pub use self::snapshot::ArtSnapshot;
+pub use self::transaction::ArtTransaction;
 
+mod arena;
 mod art;
+mod dual;
+mod error;
+mod layout;
+mod observer;
+mod rcu;
+mod setop;
+mod snapshot;
+mod transaction;
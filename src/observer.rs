@@ -0,0 +1,188 @@
+use crate::art::ArtNode;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+
+// Change made to a table, as told to its observers.
+//
+// Only changes taking effect are told: re-inserting the route already in
+// the table, or a route with the same data, is not a change. Data modified
+// in place, as with get_mut(), iter_mut() or the entry API, is told as a
+// replacement once the borrow of the data ends.
+pub enum ArtChange<P, D> {
+    Add(Arc<ArtNode<P, D>>),
+    Replace {
+        old: Arc<ArtNode<P, D>>,
+        new: Arc<ArtNode<P, D>>,
+    },
+    Delete(Arc<ArtNode<P, D>>),
+}
+
+impl<P, D> ArtChange<P, D> {
+    pub fn prefix(&self) -> &P {
+        match self {
+            ArtChange::Add(node) | ArtChange::Delete(node) => &node.prefix,
+            ArtChange::Replace { new, .. } => &new.prefix,
+        }
+    }
+}
+
+pub(crate) type ArtNotify<P, D> = Arc<dyn Fn(&ArtChange<P, D>) + Send + Sync>;
+
+struct ArtObserver<P, D> {
+    id: usize,
+    notify: ArtNotify<P, D>,
+    same: fn(&Option<D>, &Option<D>) -> bool,
+}
+
+impl<P, D> Clone for ArtObserver<P, D> {
+    fn clone(&self) -> Self {
+        ArtObserver {
+            id: self.id,
+            notify: self.notify.clone(),
+            same: self.same,
+        }
+    }
+}
+
+pub(crate) struct ArtObservers<P, D> {
+    observers: Vec<ArtObserver<P, D>>,
+    next_id: usize,
+    // Changes made in a transaction, told once it commits. Data borrowed
+    // from the table tells its change through a shared reference.
+    held: Mutex<Option<Vec<ArtChange<P, D>>>>,
+    // Hold the changes even when no one is listening.
+    record: bool,
+}
+
+impl<P, D> ArtObservers<P, D> {
+    pub(crate) fn new() -> Self {
+        ArtObservers {
+            observers: Vec::new(),
+            next_id: 0,
+            held: Mutex::new(None),
+            record: false,
+        }
+    }
+
+    pub(crate) fn subscribe(
+        &mut self,
+        notify: ArtNotify<P, D>,
+        same: fn(&Option<D>, &Option<D>) -> bool,
+    ) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.observers.push(ArtObserver { id, notify, same });
+        id
+    }
+
+    pub(crate) fn unsubscribe(&mut self, id: usize) -> bool {
+        let len = self.observers.len();
+        self.observers.retain(|observer| observer.id != id);
+        self.observers.len() != len
+    }
+
+    pub(crate) fn is_listening(&self) -> bool {
+        self.record || !self.observers.is_empty()
+    }
+
+    // The change is only made when someone is listening.
+    pub(crate) fn notify(&self, change: impl FnOnce() -> ArtChange<P, D>) {
+        if !self.is_listening() {
+            return;
+        }
+        let mut held = self.held.lock().unwrap();
+        if let Some(held) = held.as_mut() {
+            held.push(change());
+            return;
+        }
+        drop(held);
+        self.deliver(&change());
+    }
+
+    pub(crate) fn deliver(&self, change: &ArtChange<P, D>) {
+        for observer in self.observers.iter() {
+            if let ArtChange::Replace { old, new } = change {
+                if (observer.same)(&old.data, &new.data) {
+                    continue;
+                }
+            }
+            (observer.notify)(change);
+        }
+    }
+
+    // The same observers, with the changes held back.
+    pub(crate) fn hold(&self) -> Self {
+        ArtObservers {
+            observers: self.observers.clone(),
+            next_id: self.next_id,
+            held: Mutex::new(Some(Vec::new())),
+            record: false,
+        }
+    }
+
+    // The same observers, with every change held back whether someone
+    // listens or not.
+    pub(crate) fn record(&self) -> Self {
+        ArtObservers {
+            record: true,
+            ..self.hold()
+        }
+    }
+
+    pub(crate) fn release(&mut self) -> Vec<ArtChange<P, D>> {
+        self.held.get_mut().unwrap().take().unwrap_or_default()
+    }
+}
+
+// Data of a route borrowed for modification. The route is copied on the
+// first mutable access if anything else holds it, and the observers are
+// told it has been replaced once the borrow ends.
+pub struct ArtDataMut<'a, P, D> {
+    node: &'a mut Arc<ArtNode<P, D>>,
+    observers: &'a ArtObservers<P, D>,
+    // The route as it was borrowed, kept when someone is listening.
+    old: Option<Arc<ArtNode<P, D>>>,
+}
+
+impl<'a, P, D> ArtDataMut<'a, P, D> {
+    // The route must have data.
+    pub(crate) fn new(node: &'a mut Arc<ArtNode<P, D>>, observers: &'a ArtObservers<P, D>) -> Self {
+        let old = observers.is_listening().then(|| node.clone());
+        ArtDataMut {
+            node,
+            observers,
+            old,
+        }
+    }
+}
+
+impl<P, D> Deref for ArtDataMut<'_, P, D> {
+    type Target = D;
+
+    fn deref(&self) -> &Self::Target {
+        self.node.data.as_ref().unwrap()
+    }
+}
+
+impl<P, D> DerefMut for ArtDataMut<'_, P, D>
+where
+    P: Clone,
+    D: Clone,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        Arc::make_mut(self.node).data.as_mut().unwrap()
+    }
+}
+
+// Only a route borrowed mutably has been replaced, as it is still the
+// one kept otherwise.
+impl<P, D> Drop for ArtDataMut<'_, P, D> {
+    fn drop(&mut self) {
+        if let Some(old) = self.old.take() {
+            if !Arc::ptr_eq(&old, self.node) {
+                let new = self.node.clone();
+                self.observers.notify(|| ArtChange::Replace { old, new });
+            }
+        }
+    }
+}
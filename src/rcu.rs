@@ -0,0 +1,173 @@
+use crate::art::{ArtNode, ArtRoot, Prefix};
+use crate::observer::ArtChange;
+use arc_swap::ArcSwap;
+use ipnet::{Ipv4Net, Ipv6Net};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+// Routing table with lock-free readers and a single writer at a time.
+//
+// Readers load the current version of the table with an atomic pointer
+// load and never block. The writer makes its updates on a copy of the
+// current version and publishes the result with an atomic pointer swap.
+// A replaced version, together with the tables and routes only it refers
+// to, is freed once the last reader holding it is done.
+//
+// The copy is the version replaced by the previous update, brought up to
+// date by making that update again on it. Only the tables on the path of
+// an update are copied, and the table is held twice. When a reader still
+// holds the replaced version, the next update copies the root table of
+// the current version instead, which costs 2^(bits + 1) entries for a root
+// stride of `bits', or only the held ones of a sparse root.
+pub struct ArtRcuRoot<P, D> {
+    root: ArcSwap<ArtRoot<P, D>>,
+    writer: Mutex<ArtRcuWriter<P, D>>,
+}
+
+// The copy of the current version updates are made on next, if any.
+struct ArtRcuWriter<P, D> {
+    spare: Option<ArtRoot<P, D>>,
+}
+
+impl<P, D> ArtRcuRoot<P, D>
+where
+    P: Prefix + Copy,
+{
+    pub fn new(root: ArtRoot<P, D>) -> Self {
+        ArtRcuRoot {
+            root: ArcSwap::from_pointee(root),
+            writer: Mutex::new(ArtRcuWriter { spare: None }),
+        }
+    }
+
+    // The current version of the table. It is not affected by updates made
+    // after it has been loaded.
+    pub fn load(&self) -> Arc<ArtRoot<P, D>> {
+        self.root.load_full()
+    }
+
+    // Start a batch of updates to a copy of the current version, published
+    // at once on commit. Other writers wait until the transaction is done
+    // so no update is lost.
+    pub fn transaction(&self) -> ArtRcuTransaction<'_, P, D> {
+        let mut writer = self.writer.lock().unwrap();
+        let current = self.root.load();
+        let work = match writer.spare.take() {
+            Some(spare) => current.restage(spare),
+            None => current.stage_recorded(),
+        };
+        ArtRcuTransaction {
+            work,
+            root: &self.root,
+            writer,
+        }
+    }
+
+    fn update<R>(&self, f: impl FnOnce(&mut ArtRoot<P, D>) -> R) -> R {
+        let mut txn = self.transaction();
+        let ret = f(&mut txn);
+        txn.commit();
+        ret
+    }
+
+    // Observers are told the changes once they are published.
+    pub fn subscribe<F>(&self, f: F) -> usize
+    where
+        F: Fn(&ArtChange<P, D>) + Send + Sync + 'static,
+        D: PartialEq,
+    {
+        self.update(|root| root.subscribe(f))
+    }
+
+    pub fn unsubscribe(&self, id: usize) -> bool {
+        self.update(|root| root.unsubscribe(id))
+    }
+
+    pub fn insert(&self, an: &Arc<ArtNode<P, D>>, prefix: &P) -> Option<Arc<ArtNode<P, D>>> {
+        self.update(|root| root.insert(an, prefix))
+    }
+
+    pub fn delete(&self, prefix: &P) -> Option<Arc<ArtNode<P, D>>> {
+        self.update(|root| root.delete(prefix))
+    }
+
+    pub fn lookup(&self, prefix: &P) -> Option<Arc<ArtNode<P, D>>> {
+        self.root.load().lookup(prefix)
+    }
+
+    pub fn lookup_exact(&self, prefix: &P) -> Option<Arc<ArtNode<P, D>>> {
+        self.root.load().lookup_exact(prefix)
+    }
+
+    pub fn route_add(&self, prefix: P, data: D) -> Option<Arc<ArtNode<P, D>>> {
+        self.insert(&ArtNode::new(&prefix, Some(data)), &prefix)
+    }
+
+    pub fn route_delete(&self, prefix: P) -> Option<Arc<ArtNode<P, D>>> {
+        self.delete(&prefix)
+    }
+}
+
+impl<D> ArtRcuRoot<Ipv4Net, D> {
+    pub fn new_ipv4_table() -> Self {
+        Self::new(ArtRoot::new_ipv4_table())
+    }
+
+    pub fn lookup_addr(&self, addr: Ipv4Addr) -> Option<Arc<ArtNode<Ipv4Net, D>>> {
+        self.root.load().lookup_addr(addr)
+    }
+}
+
+impl<D> ArtRcuRoot<Ipv6Net, D> {
+    pub fn new_ipv6_table() -> Self {
+        Self::new(ArtRoot::new_ipv6_table())
+    }
+
+    pub fn lookup_addr(&self, addr: Ipv6Addr) -> Option<Arc<ArtNode<Ipv6Net, D>>> {
+        self.root.load().lookup_addr(addr)
+    }
+}
+
+// Same as ArtTransaction for a table with concurrent readers. A
+// transaction dropped without being committed is rolled back.
+pub struct ArtRcuTransaction<'a, P, D> {
+    root: &'a ArcSwap<ArtRoot<P, D>>,
+    work: ArtRoot<P, D>,
+    writer: MutexGuard<'a, ArtRcuWriter<P, D>>,
+}
+
+impl<P, D> ArtRcuTransaction<'_, P, D>
+where
+    P: Prefix + Copy,
+{
+    pub fn commit(mut self) {
+        let changes = self.work.take_staged();
+        let root = Arc::new(self.work);
+        let replaced = self.root.swap(root.clone());
+        root.notify_staged(&changes);
+
+        // The replaced version is left to the readers still holding it.
+        if let Ok(mut spare) = Arc::try_unwrap(replaced) {
+            spare.replay(&changes);
+            self.writer.spare = Some(spare);
+        }
+    }
+
+    // Discard the updates.
+    pub fn rollback(self) {}
+}
+
+impl<P, D> Deref for ArtRcuTransaction<'_, P, D> {
+    type Target = ArtRoot<P, D>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.work
+    }
+}
+
+impl<P, D> DerefMut for ArtRcuTransaction<'_, P, D> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.work
+    }
+}
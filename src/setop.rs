@@ -0,0 +1,67 @@
+use crate::art::{ArtNode, ArtRoot, Prefix};
+
+// Set operations taking the routes of another table into this one. The
+// tables may have different layouts. Every change made is told to the
+// observers of this table as usual.
+//
+// Routes for the same prefix in both tables are combined with `merge',
+// given the prefix, the data in this table and the data in `other'.
+impl<P, D> ArtRoot<P, D>
+where
+    P: Prefix + Copy,
+{
+    // Add the routes of `other'. Routes only in `other' are shared with
+    // it, not copied.
+    pub fn union_with<F>(&mut self, other: &ArtRoot<P, D>, mut merge: F)
+    where
+        F: FnMut(&P, Option<&D>, Option<&D>) -> Option<D>,
+    {
+        for node in other.iter() {
+            let node = match self.lookup_exact(&node.prefix) {
+                Some(route) => ArtNode::new(
+                    &node.prefix,
+                    merge(&node.prefix, route.data.as_ref(), node.data.as_ref()),
+                ),
+                None => node,
+            };
+            self.insert(&node, &node.prefix);
+        }
+    }
+
+    // Keep only the routes for prefixes also in `other'.
+    pub fn intersection<F>(&mut self, other: &ArtRoot<P, D>, mut merge: F)
+    where
+        F: FnMut(&P, Option<&D>, Option<&D>) -> Option<D>,
+    {
+        let mut merged = Vec::new();
+        self.retain(|prefix, data| match other.lookup_exact(prefix) {
+            Some(route) => {
+                merged.push(ArtNode::new(
+                    prefix,
+                    merge(prefix, data, route.data.as_ref()),
+                ));
+                true
+            }
+            None => false,
+        });
+        for node in merged {
+            self.insert(&node, &node.prefix);
+        }
+    }
+
+    // Delete the routes for prefixes also in `other'.
+    pub fn difference(&mut self, other: &ArtRoot<P, D>) {
+        self.retain(|prefix, _| other.lookup_exact(prefix).is_none());
+    }
+
+    // Keep only the routes covered by a route in `other', that is for
+    // which `other' has a route for the same or a shorter prefix.
+    pub fn intersection_covered(&mut self, other: &ArtRoot<P, D>) {
+        self.retain(|prefix, _| other.lookup(prefix).is_some());
+    }
+
+    // Delete the routes covered by a route in `other'.
+    pub fn difference_covered(&mut self, other: &ArtRoot<P, D>) {
+        self.retain(|prefix, _| other.lookup(prefix).is_none());
+    }
+}
@@ -0,0 +1,63 @@
+use crate::art::{ArtIter, ArtNode, ArtRoot, Prefix};
+use std::ops::Deref;
+use std::sync::Arc;
+
+// Read-only view of a table as it was when the snapshot was taken.
+//
+// The snapshot holds the tables of the live table at that time. The live
+// table copies a table before modifying it as long as the snapshot is
+// around, so only the paths of later updates are copied, everything else
+// is shared, and nothing done to the live table shows up in the snapshot.
+// Taking a snapshot does not copy anything.
+pub struct ArtSnapshot<P, D> {
+    root: ArtRoot<P, D>,
+}
+
+impl<P, D> ArtRoot<P, D>
+where
+    P: Prefix + Copy,
+{
+    pub fn snapshot(&self) -> ArtSnapshot<P, D> {
+        ArtSnapshot { root: self.share() }
+    }
+}
+
+impl<P, D> ArtSnapshot<P, D>
+where
+    P: Prefix + Copy,
+{
+    // A table starting out with the routes of the snapshot.
+    pub fn to_root(&self) -> ArtRoot<P, D> {
+        self.root.share()
+    }
+}
+
+// Every read operation of the table is available on the snapshot.
+impl<P, D> Deref for ArtSnapshot<P, D> {
+    type Target = ArtRoot<P, D>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.root
+    }
+}
+
+impl<P, D> Clone for ArtSnapshot<P, D>
+where
+    P: Prefix + Copy,
+{
+    fn clone(&self) -> Self {
+        self.root.snapshot()
+    }
+}
+
+impl<P, D> IntoIterator for &ArtSnapshot<P, D>
+where
+    P: Prefix + Copy,
+{
+    type Item = Arc<ArtNode<P, D>>;
+    type IntoIter = ArtIter<P, D>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.root.iter()
+    }
+}
@@ -0,0 +1,60 @@
+use crate::art::{ArtRoot, Prefix};
+use std::ops::{Deref, DerefMut};
+
+// Batch of updates to a table applied all at once.
+//
+// The updates are made to a copy of the table sharing its tables, so only
+// the paths of the updates are copied and the table itself is left alone
+// until commit() puts the copy in its place. Anyone looking at the table,
+// through a snapshot taken before or after the commit, sees either none or
+// all of the updates. Every operation of the table is available on the
+// transaction, and sees the updates made so far. Observers subscribed in
+// the transaction stay with the table on commit.
+//
+// A transaction dropped without being committed is rolled back.
+pub struct ArtTransaction<'a, P, D> {
+    root: &'a mut ArtRoot<P, D>,
+    work: ArtRoot<P, D>,
+}
+
+impl<P, D> ArtRoot<P, D>
+where
+    P: Prefix + Copy,
+{
+    pub fn transaction(&mut self) -> ArtTransaction<'_, P, D> {
+        ArtTransaction {
+            work: self.stage(),
+            root: self,
+        }
+    }
+}
+
+impl<P, D> ArtTransaction<'_, P, D>
+where
+    P: Prefix + Copy,
+{
+    // The observers of the table are told the changes once they are all
+    // in place.
+    pub fn commit(mut self) {
+        let changes = self.work.take_staged();
+        *self.root = self.work;
+        self.root.notify_staged(&changes);
+    }
+
+    // Discard the updates.
+    pub fn rollback(self) {}
+}
+
+impl<P, D> Deref for ArtTransaction<'_, P, D> {
+    type Target = ArtRoot<P, D>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.work
+    }
+}
+
+impl<P, D> DerefMut for ArtTransaction<'_, P, D> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.work
+    }
+}
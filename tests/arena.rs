@@ -0,0 +1,164 @@
+use art::*;
+use ipnet::{Ipv4Net, Ipv6Net};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::net::Ipv4Addr;
+use std::time;
+
+fn lookup_assert(top: &ArtArenaRoot<Ipv4Net, u32>, addr: &str, data: Option<u32>) {
+    let addr: Ipv4Addr = addr.parse().unwrap();
+    let node = top.lookup(&Ipv4Net::from(addr));
+    assert_eq!(node.and_then(|node| node.data), data);
+    let node = top.lookup_addr(addr);
+    assert_eq!(node.and_then(|node| node.data), data);
+}
+
+#[test]
+fn arena_lookup() {
+    let mut top = ArtArenaRoot::<Ipv4Net, u32>::new_ipv4_table();
+
+    top.route_ipv4_add("0.0.0.0/0".parse().unwrap(), 0);
+    top.route_ipv4_add("10.0.0.0/8".parse().unwrap(), 8);
+    top.route_ipv4_add("10.1.0.0/16".parse().unwrap(), 16);
+    top.route_ipv4_add("10.1.1.0/24".parse().unwrap(), 24);
+    top.route_ipv4_add("10.1.1.1/32".parse().unwrap(), 32);
+    assert_eq!(top.iter().count(), 5);
+
+    lookup_assert(&top, "192.168.0.1", Some(0));
+    lookup_assert(&top, "10.2.0.1", Some(8));
+    lookup_assert(&top, "10.1.2.1", Some(16));
+    lookup_assert(&top, "10.1.1.2", Some(24));
+    lookup_assert(&top, "10.1.1.1", Some(32));
+
+    assert!(top.lookup_exact(&"10.1.0.0/16".parse().unwrap()).is_some());
+    assert!(top.lookup_exact(&"10.1.0.0/17".parse().unwrap()).is_none());
+    assert!(top.lookup_exact(&"10.1.1.0/25".parse().unwrap()).is_none());
+
+    assert_eq!(
+        top.route_ipv4_delete("10.1.1.0/24".parse().unwrap())
+            .unwrap()
+            .data,
+        Some(24)
+    );
+    lookup_assert(&top, "10.1.1.2", Some(16));
+    lookup_assert(&top, "10.1.1.1", Some(32));
+
+    assert_eq!(
+        top.route_ipv4_delete("0.0.0.0/0".parse().unwrap())
+            .unwrap()
+            .data,
+        Some(0)
+    );
+    lookup_assert(&top, "192.168.0.1", None);
+    assert_eq!(top.iter().count(), 3);
+}
+
+#[test]
+fn arena_insert_delete_return() {
+    let mut top = ArtArenaRoot::<Ipv4Net, u32>::new_ipv4_table();
+    let prefix: Ipv4Net = "10.1.0.0/16".parse().unwrap();
+
+    assert!(top.route_ipv4_add(prefix, 1).is_none());
+    assert_eq!(top.route_ipv4_add(prefix, 2).unwrap().data, Some(1));
+    lookup_assert(&top, "10.1.0.1", Some(2));
+
+    // A less specific route alloted to the index is not deleted.
+    assert!(top
+        .route_ipv4_delete("10.1.0.0/17".parse().unwrap())
+        .is_none());
+    assert!(top
+        .route_ipv4_delete("10.0.0.0/8".parse().unwrap())
+        .is_none());
+    assert_eq!(top.route_ipv4_delete(prefix).unwrap().data, Some(2));
+    assert!(top.route_ipv4_delete(prefix).is_none());
+    assert_eq!(top.iter().count(), 0);
+}
+
+#[test]
+fn arena_reuse() {
+    let mut top = ArtArenaRoot::<Ipv4Net, u32>::new_ipv4_table();
+
+    // Freed tables and routes are reused by the next insertions.
+    for round in 0..4 {
+        for i in 0..256u32 {
+            let prefix = Ipv4Net::new(Ipv4Addr::from(0x0a000000 | (i << 8)), 24).unwrap();
+            assert!(top.route_ipv4_add(prefix, round).is_none());
+        }
+        lookup_assert(&top, "10.0.3.1", Some(round));
+        lookup_assert(&top, "10.1.0.1", None);
+        assert_eq!(top.iter().count(), 256);
+        assert_eq!(top.len(), 256);
+
+        for i in 0..256u32 {
+            let prefix = Ipv4Net::new(Ipv4Addr::from(0x0a000000 | (i << 8)), 24).unwrap();
+            assert_eq!(top.route_ipv4_delete(prefix).unwrap().data, Some(round));
+        }
+        assert_eq!(top.iter().count(), 0);
+        assert!(top.is_empty());
+    }
+
+    for i in 0..256u32 {
+        let prefix = Ipv4Net::new(Ipv4Addr::from(0x0a000000 | (i << 4)), 28).unwrap();
+        top.route_ipv4_add(prefix, 28);
+    }
+    top.clear();
+    assert!(top.is_empty());
+    assert_eq!(top.iter().count(), 0);
+    lookup_assert(&top, "10.0.3.1", None);
+    top.route_ipv4_add("10.0.3.0/24".parse().unwrap(), 24);
+    lookup_assert(&top, "10.0.3.1", Some(24));
+    assert_eq!(top.len(), 1);
+}
+
+#[test]
+fn arena_ipv6_route_random1() {
+    let mut arena = ArtArenaRoot::<Ipv6Net, i32>::new_ipv6_table();
+    let mut top = ArtRoot::<Ipv6Net, i32>::new_ipv6_table();
+
+    let file = File::open("tests/data/v6routes-random1.txt").unwrap();
+    let bufferd = BufReader::new(file);
+    let prefixes: Vec<Ipv6Net> = bufferd
+        .lines()
+        .map(|line| line.unwrap().parse().unwrap())
+        .collect();
+
+    let now = time::Instant::now();
+    for (i, prefix) in prefixes.iter().enumerate() {
+        arena.route_ipv6_add(*prefix, i as i32);
+    }
+    println!("arena_ipv6_route_random1 insert {:?}", now.elapsed());
+    for (i, prefix) in prefixes.iter().enumerate() {
+        top.route_ipv6_add(*prefix, i as i32);
+    }
+    assert_eq!(arena.iter().count(), 24470);
+
+    // Both backends agree on every lookup.
+    let now = time::Instant::now();
+    for _ in 0..10 {
+        for prefix in prefixes.iter() {
+            assert!(arena.lookup(prefix).is_some());
+        }
+    }
+    println!("arena_ipv6_route_random1 lookup {:?}", now.elapsed());
+    for prefix in prefixes.iter() {
+        let expected = top.lookup_addr(prefix.addr()).unwrap();
+        let node = arena.lookup_addr(prefix.addr()).unwrap();
+        assert_eq!(node.prefix, expected.prefix);
+        assert_eq!(node.data, expected.data);
+        assert_eq!(arena.lookup_exact(prefix).unwrap().prefix, *prefix);
+    }
+
+    for prefix in prefixes.iter().step_by(2) {
+        assert!(arena.route_ipv6_delete(*prefix).is_some());
+        top.route_ipv6_delete(*prefix);
+    }
+    for prefix in prefixes.iter() {
+        let expected = top.lookup(prefix).map(|node| node.prefix);
+        assert_eq!(arena.lookup(prefix).map(|node| node.prefix), expected);
+    }
+
+    for prefix in prefixes.iter().skip(1).step_by(2) {
+        assert!(arena.route_ipv6_delete(*prefix).is_some());
+    }
+    assert_eq!(arena.iter().count(), 0);
+}
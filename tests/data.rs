@@ -1,7 +1,8 @@
 use art::*;
-use ipnet::Ipv4Net;
+use ipnet::{Ipv4Net, Ipv6Net};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::net::Ipv6Addr;
 use std::time;
 
 #[test]
@@ -17,7 +18,7 @@ fn ipv4_route_random1() {
         let prefix: Ipv4Net = line.parse().unwrap();
         top.route_ipv4_add(prefix, 0);
     }
-    assert_eq!(top.iter().count(), 569770);
+    assert_eq!(top.len(), 569770);
 
     let file = File::open("tests/data/v4routes-random1.txt").unwrap();
     let bufferd = BufReader::new(file);
@@ -28,7 +29,7 @@ fn ipv4_route_random1() {
         top.route_ipv4_delete(prefix);
     }
 
-    assert_eq!(top.iter().count(), 0);
+    assert!(top.is_empty());
     println!("ipv4_route_random1 {:?}", now.elapsed());
 }
 
@@ -45,7 +46,7 @@ fn ipv4_route_random1_lookup_exact() {
         let prefix: Ipv4Net = line.parse().unwrap();
         top.route_ipv4_add(prefix, 0);
     }
-    assert_eq!(top.iter().count(), 569770);
+    assert_eq!(top.len(), 569770);
 
     let file = File::open("tests/data/v4routes-random1.txt").unwrap();
     let bufferd = BufReader::new(file);
@@ -59,53 +60,465 @@ fn ipv4_route_random1_lookup_exact() {
     println!("ipv4_route_random1_lookup_exact {:?}", now.elapsed());
 }
 
-// #[test]
-// fn ipv6_route_random1() {
-//     let now = time::Instant::now();
-//     let mut top = ArtRoot::<Ipv4Net, i32>::new_ipv6_table();
-
-//     let file = File::open("tests/data/v6routes-random1.txt").unwrap();
-//     let bufferd = BufReader::new(file);
-
-//     for line in bufferd.lines() {
-//         let line = line.unwrap();
-//         top.route_ipv6_add(&line, 0);
-//     }
-//     assert_eq!(top.iter().count(), 24470);
-
-//     let file = File::open("tests/data/v6routes-random1.txt").unwrap();
-//     let bufferd = BufReader::new(file);
-
-//     for line in bufferd.lines() {
-//         let line = line.unwrap();
-//         top.route_ipv6_delete(&line);
-//     }
-
-//     assert_eq!(top.iter().count(), 0);
-//     println!("ipv6_route_random1 {:?}", now.elapsed());
-// }
-
-// #[test]
-// fn ipv6_route_random1_lookup_exact() {
-//     let now = time::Instant::now();
-//     let mut top = ArtRoot::<Ipv4Net, i32>::new_ipv6_table();
-
-//     let file = File::open("tests/data/v6routes-random1.txt").unwrap();
-//     let bufferd = BufReader::new(file);
-
-//     for line in bufferd.lines() {
-//         let line = line.unwrap();
-//         top.route_ipv6_add(&line, 0);
-//     }
-//     assert_eq!(top.iter().count(), 24470);
-
-//     let file = File::open("tests/data/v6routes-random1.txt").unwrap();
-//     let bufferd = BufReader::new(file);
-
-//     for line in bufferd.lines() {
-//         let line = line.unwrap();
-//         let result = top.route_ipv6_lookup_exact(&line);
-//         assert!(result.is_some());
-//     }
-//     println!("ipv6_route_random1_lookup_exact {:?}", now.elapsed());
-// }
+#[test]
+fn ipv6_route_random1() {
+    let now = time::Instant::now();
+    let mut top = ArtRoot::<Ipv6Net, i32>::new_ipv6_table();
+
+    let file = File::open("tests/data/v6routes-random1.txt").unwrap();
+    let bufferd = BufReader::new(file);
+
+    for line in bufferd.lines() {
+        let line = line.unwrap();
+        let prefix: Ipv6Net = line.parse().unwrap();
+        top.route_ipv6_add(prefix, 0);
+    }
+    assert_eq!(top.len(), 24470);
+
+    let file = File::open("tests/data/v6routes-random1.txt").unwrap();
+    let bufferd = BufReader::new(file);
+
+    for line in bufferd.lines() {
+        let line = line.unwrap();
+        let prefix: Ipv6Net = line.parse().unwrap();
+        top.route_ipv6_delete(prefix);
+    }
+
+    assert_eq!(top.iter().count(), 0);
+
+    // All sub-tables have been reclaimed.
+    let root = top.root();
+    for i in 1..32 {
+        assert!(!matches!(root.get_entry(i), ArtEntry::Table(_)));
+    }
+    println!("ipv6_route_random1 {:?}", now.elapsed());
+}
+
+#[test]
+fn ipv6_route_random2_delete() {
+    let now = time::Instant::now();
+    let mut top = ArtRoot::<Ipv6Net, i32>::new_ipv6_table();
+
+    let file = File::open("tests/data/v6routes-random1.txt").unwrap();
+    let bufferd = BufReader::new(file);
+
+    for line in bufferd.lines() {
+        let line = line.unwrap();
+        let prefix: Ipv6Net = line.parse().unwrap();
+        top.route_ipv6_add(prefix, 0);
+    }
+    assert_eq!(top.iter().count(), 24470);
+
+    // Same route set in a different order.
+    let file = File::open("tests/data/v6routes-random2.txt").unwrap();
+    let bufferd = BufReader::new(file);
+
+    for line in bufferd.lines() {
+        let line = line.unwrap();
+        let prefix: Ipv6Net = line.parse().unwrap();
+        top.route_ipv6_delete(prefix);
+    }
+
+    assert_eq!(top.iter().count(), 0);
+    println!("ipv6_route_random2_delete {:?}", now.elapsed());
+}
+
+#[test]
+fn ipv6_route_random1_lookup_exact() {
+    let now = time::Instant::now();
+    let mut top = ArtRoot::<Ipv6Net, i32>::new_ipv6_table();
+
+    let file = File::open("tests/data/v6routes-random1.txt").unwrap();
+    let bufferd = BufReader::new(file);
+
+    for line in bufferd.lines() {
+        let line = line.unwrap();
+        let prefix: Ipv6Net = line.parse().unwrap();
+        top.route_ipv6_add(prefix, 0);
+    }
+    assert_eq!(top.iter().count(), 24470);
+
+    let file = File::open("tests/data/v6routes-random1.txt").unwrap();
+    let bufferd = BufReader::new(file);
+
+    for line in bufferd.lines() {
+        let line = line.unwrap();
+        let prefix: Ipv6Net = line.parse().unwrap();
+        let result = top.route_ipv6_lookup_exact(prefix);
+        assert!(result.is_some());
+        assert_eq!(result.unwrap().prefix, prefix);
+
+        // The first address of a prefix matches the prefix or one of its
+        // more specifics.
+        let result = top.lookup_addr(prefix.addr()).unwrap();
+        assert!(prefix.contains(&result.prefix));
+    }
+    println!("ipv6_route_random1_lookup_exact {:?}", now.elapsed());
+}
+
+#[test]
+fn ipv6_route_random1_strides() {
+    let now = time::Instant::now();
+    let mut strides = [16u8, 16u8].to_vec();
+    strides.extend([8u8; 12]);
+    let mut top = ArtRoot::<Ipv6Net, i32>::new(14, strides, 128).unwrap();
+
+    let file = File::open("tests/data/v6routes-random1.txt").unwrap();
+    let bufferd = BufReader::new(file);
+
+    for line in bufferd.lines() {
+        let line = line.unwrap();
+        let prefix: Ipv6Net = line.parse().unwrap();
+        top.route_ipv6_add(prefix, 0);
+    }
+    assert_eq!(top.iter().count(), 24470);
+
+    let file = File::open("tests/data/v6routes-random2.txt").unwrap();
+    let bufferd = BufReader::new(file);
+
+    for line in bufferd.lines() {
+        let line = line.unwrap();
+        let prefix: Ipv6Net = line.parse().unwrap();
+        let result = top.route_ipv6_lookup_exact(prefix);
+        assert_eq!(result.unwrap().prefix, prefix);
+        top.route_ipv6_delete(prefix);
+    }
+
+    assert_eq!(top.iter().count(), 0);
+    println!("ipv6_route_random1_strides {:?}", now.elapsed());
+}
+
+#[test]
+fn ipv6_route_random1_lookup() {
+    let mut top = ArtRoot::<Ipv6Net, i32>::new_ipv6_table();
+
+    let file = File::open("tests/data/v6routes-random1.txt").unwrap();
+    let bufferd = BufReader::new(file);
+    let prefixes: Vec<Ipv6Net> = bufferd
+        .lines()
+        .map(|line| line.unwrap().parse().unwrap())
+        .collect();
+
+    let now = time::Instant::now();
+    for prefix in prefixes.iter() {
+        top.route_ipv6_add(*prefix, 0);
+    }
+    println!("ipv6_route_random1_lookup insert {:?}", now.elapsed());
+
+    let now = time::Instant::now();
+    for _ in 0..10 {
+        for prefix in prefixes.iter() {
+            assert!(top.lookup(prefix).is_some());
+        }
+    }
+    println!("ipv6_route_random1_lookup lookup {:?}", now.elapsed());
+
+    let now = time::Instant::now();
+    for _ in 0..10 {
+        for prefix in prefixes.iter() {
+            assert!(top.lookup_exact(prefix).is_some());
+        }
+    }
+    println!("ipv6_route_random1_lookup lookup_exact {:?}", now.elapsed());
+
+    let now = time::Instant::now();
+    for _ in 0..10 {
+        assert_eq!(top.iter().count(), 24470);
+    }
+    println!("ipv6_route_random1_lookup iter {:?}", now.elapsed());
+}
+
+#[test]
+fn ipv6_route_random1_sorted() {
+    let mut top = ArtRoot::<Ipv6Net, i32>::new_ipv6_table();
+
+    let file = File::open("tests/data/v6routes-random1.txt").unwrap();
+    let bufferd = BufReader::new(file);
+
+    for line in bufferd.lines() {
+        let line = line.unwrap();
+        let prefix: Ipv6Net = line.parse().unwrap();
+        top.route_ipv6_add(prefix, 0);
+    }
+
+    let mut prefixes: Vec<Ipv6Net> = top.iter().map(|node| node.prefix).collect();
+    prefixes.sort_by_key(|prefix| (prefix.addr(), prefix.prefix_len()));
+
+    let now = time::Instant::now();
+    let sorted: Vec<Ipv6Net> = top.iter_sorted().map(|node| node.prefix).collect();
+    println!("ipv6_route_random1_sorted {:?}", now.elapsed());
+    assert_eq!(sorted, prefixes);
+}
+
+#[test]
+fn ipv6_route_random1_more_specifics() {
+    let mut top = ArtRoot::<Ipv6Net, i32>::new_ipv6_table();
+
+    let file = File::open("tests/data/v6routes-random1.txt").unwrap();
+    let bufferd = BufReader::new(file);
+    let prefixes: Vec<Ipv6Net> = bufferd
+        .lines()
+        .map(|line| line.unwrap().parse().unwrap())
+        .collect();
+
+    for prefix in prefixes.iter() {
+        top.route_ipv6_add(*prefix, 0);
+    }
+    let sorted: Vec<Ipv6Net> = top.iter_sorted().map(|node| node.prefix).collect();
+
+    for prefix in prefixes.iter().step_by(499).map(|prefix| prefix.trunc()) {
+        for plen in [16, 24, 32, 40, 48] {
+            let prefix = Ipv6Net::new(prefix.addr(), plen.min(prefix.prefix_len()))
+                .unwrap()
+                .trunc();
+            let expected: Vec<Ipv6Net> = sorted
+                .iter()
+                .filter(|route| prefix.contains(*route) && route.prefix_len() > prefix.prefix_len())
+                .copied()
+                .collect();
+            let routes: Vec<Ipv6Net> = top
+                .iter_more_specifics(&prefix)
+                .map(|node| node.prefix)
+                .collect();
+            assert_eq!(routes, expected);
+        }
+    }
+}
+
+#[test]
+fn ipv6_route_random1_less_specifics() {
+    let mut top = ArtRoot::<Ipv6Net, i32>::new_ipv6_table();
+
+    let file = File::open("tests/data/v6routes-random1.txt").unwrap();
+    let bufferd = BufReader::new(file);
+    let prefixes: Vec<Ipv6Net> = bufferd
+        .lines()
+        .map(|line| line.unwrap().parse().unwrap())
+        .collect();
+
+    for prefix in prefixes.iter() {
+        top.route_ipv6_add(*prefix, 0);
+    }
+    let sorted: Vec<Ipv6Net> = top.iter_sorted().map(|node| node.prefix).collect();
+
+    for prefix in prefixes.iter().step_by(97) {
+        let prefix = Ipv6Net::new(prefix.addr(), 128).unwrap();
+        let expected: Vec<Ipv6Net> = sorted
+            .iter()
+            .filter(|route| route.contains(&prefix))
+            .copied()
+            .collect();
+        let routes: Vec<Ipv6Net> = top
+            .iter_less_specifics(&prefix)
+            .map(|node| node.prefix)
+            .collect();
+        assert_eq!(routes, expected);
+        assert_eq!(
+            routes.last(),
+            top.lookup(&prefix).as_ref().map(|node| &node.prefix)
+        );
+    }
+}
+
+#[test]
+fn ipv6_route_random1_entry() {
+    let mut top = ArtRoot::<Ipv6Net, i32>::new_ipv6_table();
+
+    let file = File::open("tests/data/v6routes-random1.txt").unwrap();
+    let bufferd = BufReader::new(file);
+    let prefixes: Vec<Ipv6Net> = bufferd
+        .lines()
+        .map(|line| line.unwrap().parse().unwrap())
+        .collect();
+
+    for prefix in prefixes.iter() {
+        *top.entry(*prefix).unwrap().or_insert(0) += 1;
+    }
+    for prefix in prefixes.iter() {
+        top.entry(*prefix).unwrap().and_modify(|n| *n += 1);
+    }
+    for prefix in prefixes.iter() {
+        assert_eq!(top.lookup_exact(prefix).unwrap().data, Some(2));
+    }
+
+    for prefix in prefixes.iter() {
+        if let ArtRouteEntry::Occupied(entry) = top.entry(*prefix).unwrap() {
+            assert_eq!(entry.remove().prefix, *prefix);
+        }
+    }
+    assert_eq!(top.iter().count(), 0);
+
+    // All sub-tables have been reclaimed.
+    let root = top.root();
+    for i in 1..32 {
+        assert!(!matches!(root.get_entry(i), ArtEntry::Table(_)));
+    }
+}
+
+#[test]
+fn ipv6_route_random1_collect() {
+    let file = File::open("tests/data/v6routes-random1.txt").unwrap();
+    let bufferd = BufReader::new(file);
+
+    let now = time::Instant::now();
+    let top: ArtRoot<Ipv6Net, usize> = bufferd
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (line.unwrap().parse().unwrap(), i))
+        .collect();
+    println!("ipv6_route_random1_collect {:?}", now.elapsed());
+    assert_eq!(top.len(), 24470);
+
+    let mut expected: Vec<(Ipv6Net, usize)> = top
+        .iter()
+        .map(|node| (node.prefix, node.data.unwrap()))
+        .collect();
+    let mut routes: Vec<(Ipv6Net, usize)> = top.into_iter().collect();
+    expected.sort();
+    routes.sort();
+    assert_eq!(routes, expected);
+}
+
+#[test]
+fn ipv6_route_random1_drain_filter() {
+    let mut top = ArtRoot::<Ipv6Net, usize>::new_ipv6_table();
+    let mut expected = ArtRoot::<Ipv6Net, usize>::new_ipv6_table();
+
+    let file = File::open("tests/data/v6routes-random1.txt").unwrap();
+    let bufferd = BufReader::new(file);
+    let prefixes: Vec<Ipv6Net> = bufferd
+        .lines()
+        .map(|line| line.unwrap().parse().unwrap())
+        .collect();
+
+    for (i, prefix) in prefixes.iter().enumerate() {
+        top.route_ipv6_add(*prefix, i % 3);
+        expected.route_ipv6_add(*prefix, i % 3);
+    }
+    for prefix in prefixes.iter() {
+        if expected.lookup_exact(prefix).unwrap().data == Some(0) {
+            expected.route_ipv6_delete(*prefix);
+        }
+    }
+
+    let now = time::Instant::now();
+    let removed = top.drain_filter(|_, data| data == Some(&0));
+    println!("ipv6_route_random1_drain_filter {:?}", now.elapsed());
+    assert!(removed.iter().all(|node| node.data == Some(0)));
+    assert_eq!(removed.len() + expected.len(), 24470);
+    assert_eq!(top.len(), expected.len());
+
+    for prefix in prefixes.iter() {
+        let node = top.lookup_addr(prefix.addr()).map(|node| node.prefix);
+        assert_eq!(
+            node,
+            expected.lookup_addr(prefix.addr()).map(|node| node.prefix)
+        );
+        let node = top.lookup(prefix).map(|node| node.prefix);
+        assert_eq!(node, expected.lookup(prefix).map(|node| node.prefix));
+    }
+
+    top.retain(|_, _| false);
+    let root = top.root();
+    for i in 1..32 {
+        assert!(matches!(root.get_entry(i), ArtEntry::None));
+    }
+}
+
+#[test]
+fn ipv6_route_random1_layouts() {
+    let file = File::open("tests/data/v6routes-random1.txt").unwrap();
+    let bufferd = BufReader::new(file);
+    let prefixes: Vec<Ipv6Net> = bufferd
+        .lines()
+        .map(|line| line.unwrap().parse().unwrap())
+        .collect();
+
+    let top: ArtRoot<Ipv6Net, usize> = prefixes.iter().copied().zip(0..).collect();
+    for layout in [
+        ArtLayout::ipv6_8x16(),
+        ArtLayout::ipv6_16_16_8x12(),
+        // Sparse tables.
+        ArtLayout::new([48, 40, 40].to_vec(), 128).unwrap(),
+        ArtLayout::new([56, 56, 16].to_vec(), 128).unwrap(),
+    ] {
+        let now = time::Instant::now();
+        let mut other = ArtRoot::<Ipv6Net, usize>::with_layout(&layout).unwrap();
+        other.extend(prefixes.iter().copied().zip(0..));
+        println!(
+            "ipv6_route_random1_layouts {:?} {:?}",
+            layout.bits(),
+            now.elapsed()
+        );
+        assert_eq!(other.len(), 24470);
+
+        for prefix in prefixes.iter() {
+            let expected = top.lookup_addr(prefix.addr()).unwrap();
+            let node = other.lookup_addr(prefix.addr()).unwrap();
+            assert_eq!(node.prefix, expected.prefix);
+            assert_eq!(node.data, expected.data);
+            let node = other.lookup(prefix).unwrap();
+            assert_eq!(node.prefix, *prefix);
+        }
+
+        for prefix in prefixes.iter() {
+            assert!(other.route_ipv6_delete(*prefix).is_some());
+        }
+        assert!(other.is_empty());
+    }
+}
+
+#[test]
+fn ipv6_route_random1_diff() {
+    let file = File::open("tests/data/v6routes-random1.txt").unwrap();
+    let bufferd = BufReader::new(file);
+    let prefixes: Vec<Ipv6Net> = bufferd
+        .lines()
+        .map(|line| line.unwrap().parse().unwrap())
+        .collect();
+
+    let old: ArtRoot<Ipv6Net, usize> = prefixes.iter().copied().zip(0..).collect();
+    let mut new = old.snapshot().to_root();
+    let mut expected = Vec::new();
+    for (i, prefix) in prefixes.iter().enumerate() {
+        match i % 7 {
+            0 => {
+                new.route_ipv6_delete(*prefix);
+                expected.push((*prefix, Some(i), None));
+            }
+            1 => {
+                new.route_ipv6_add(*prefix, i + 1);
+                expected.push((*prefix, Some(i), Some(i + 1)));
+            }
+            2 => {
+                // Same data, not a change.
+                new.route_ipv6_add(*prefix, i);
+            }
+            _ => {}
+        }
+    }
+    let last = Ipv6Net::new(Ipv6Addr::new(0xffff, 0, 0, 0, 0, 0, 0, 0), 128).unwrap();
+    new.route_ipv6_add(last, 0);
+    expected.push((last, None, Some(0)));
+    expected.sort_by_key(|a| (a.0.addr(), a.0.prefix_len()));
+
+    let now = time::Instant::now();
+    let changes: Vec<_> = old
+        .diff(&new)
+        .map(|change| match change {
+            ArtChange::Add(node) => (node.prefix, None, node.data),
+            ArtChange::Replace { old, new } => (new.prefix, old.data, new.data),
+            ArtChange::Delete(node) => (node.prefix, node.data, None),
+        })
+        .collect();
+    println!("ipv6_route_random1_diff {:?}", now.elapsed());
+    assert_eq!(changes, expected);
+
+    let other: ArtRoot<Ipv6Net, usize> = {
+        let mut other = ArtRoot::with_layout(&ArtLayout::ipv6_8x16()).unwrap();
+        other.extend(new.iter().map(|node| (node.prefix, node.data.unwrap())));
+        other
+    };
+    assert_eq!(old.diff(&other).count(), expected.len());
+    assert_eq!(new.diff(&other).count(), 0);
+}
@@ -0,0 +1,129 @@
+use art::*;
+use ipnet::Ipv4Net;
+
+fn changes(old: &ArtRoot<Ipv4Net, u32>, new: &ArtRoot<Ipv4Net, u32>) -> Vec<String> {
+    old.diff(new)
+        .map(|change| match change {
+            ArtChange::Add(node) => format!("+{} {}", node.prefix, node.data.unwrap()),
+            ArtChange::Replace { old, new } => format!(
+                "~{} {} {}",
+                new.prefix,
+                old.data.unwrap(),
+                new.data.unwrap()
+            ),
+            ArtChange::Delete(node) => format!("-{} {}", node.prefix, node.data.unwrap()),
+        })
+        .collect()
+}
+
+fn table(layout: &ArtLayout, routes: &[(&str, u32)]) -> ArtRoot<Ipv4Net, u32> {
+    let mut top = ArtRoot::with_layout(layout).unwrap();
+    for (prefix, data) in routes {
+        top.route_ipv4_add(prefix.parse().unwrap(), *data);
+    }
+    top
+}
+
+#[test]
+fn diff_basic() {
+    let layout = ArtLayout::ipv4_8_4_4_4_4_4_4();
+    let old = table(
+        &layout,
+        &[
+            ("0.0.0.0/0", 0),
+            ("10.0.0.0/8", 8),
+            ("10.1.0.0/16", 16),
+            ("10.1.1.0/24", 24),
+            ("10.1.1.1/32", 32),
+            ("192.168.0.0/16", 16),
+        ],
+    );
+    let new = table(
+        &layout,
+        &[
+            ("0.0.0.0/0", 0),
+            ("10.0.0.0/8", 9),
+            ("10.1.1.0/24", 24),
+            ("10.1.1.0/25", 25),
+            ("10.1.1.1/32", 33),
+            ("172.16.0.0/12", 12),
+        ],
+    );
+
+    assert_eq!(
+        changes(&old, &new),
+        [
+            "~10.0.0.0/8 8 9",
+            "-10.1.0.0/16 16",
+            "+10.1.1.0/25 25",
+            "~10.1.1.1/32 32 33",
+            "+172.16.0.0/12 12",
+            "-192.168.0.0/16 16",
+        ]
+    );
+    assert_eq!(
+        changes(&new, &old),
+        [
+            "~10.0.0.0/8 9 8",
+            "+10.1.0.0/16 16",
+            "-10.1.1.0/25 25",
+            "~10.1.1.1/32 33 32",
+            "-172.16.0.0/12 12",
+            "+192.168.0.0/16 16",
+        ]
+    );
+    assert!(changes(&old, &old).is_empty());
+    assert_eq!(changes(&old, &table(&layout, &[])).len(), old.len());
+    assert_eq!(changes(&table(&layout, &[]), &new).len(), new.len());
+
+    // Across layouts the routes are merged in the same order.
+    for layout in [ArtLayout::ipv4_8_8_8_8(), ArtLayout::ipv4_16_8_8()] {
+        let mut other = ArtRoot::with_layout(&layout).unwrap();
+        other.extend(new.iter().map(|node| (node.prefix, node.data.unwrap())));
+        assert_eq!(changes(&old, &other), changes(&old, &new));
+        assert!(changes(&new, &other).is_empty());
+    }
+}
+
+#[test]
+fn diff_snapshot() {
+    let mut top = ArtRoot::<Ipv4Net, u32>::new_ipv4_table();
+    for i in 0..=255u32 {
+        top.route_ipv4_add(Ipv4Net::new([10, i as u8, 0, 0].into(), 16).unwrap(), i);
+        top.route_ipv4_add(Ipv4Net::new([10, i as u8, 1, 0].into(), 24).unwrap(), i);
+    }
+
+    let snapshot = top.snapshot();
+    assert_eq!(top.diff(&snapshot).count(), 0);
+
+    top.route_ipv4_add("10.7.1.0/24".parse().unwrap(), 1000);
+    top.route_ipv4_delete("10.200.0.0/16".parse().unwrap());
+    top.route_ipv4_add("10.255.255.255/32".parse().unwrap(), 32);
+    assert_eq!(
+        changes(&snapshot, &top),
+        [
+            "~10.7.1.0/24 7 1000",
+            "-10.200.0.0/16 200",
+            "+10.255.255.255/32 32"
+        ]
+    );
+
+    // Data modified in place is seen, as the data is compared.
+    *top.get_mut(&"10.3.0.0/16".parse().unwrap()).unwrap() = 4;
+    assert_eq!(changes(&snapshot, &top)[0], "~10.3.0.0/16 3 4");
+
+    // Applying the changes to the snapshot gives the live table.
+    let mut copy = snapshot.to_root();
+    for change in snapshot.diff(&top) {
+        match change {
+            ArtChange::Add(node) | ArtChange::Replace { new: node, .. } => {
+                copy.route_ipv4_add(node.prefix, node.data.unwrap());
+            }
+            ArtChange::Delete(node) => {
+                copy.route_ipv4_delete(node.prefix);
+            }
+        }
+    }
+    assert_eq!(copy.diff(&top).count(), 0);
+    assert_eq!(copy.len(), top.len());
+}
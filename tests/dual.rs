@@ -0,0 +1,174 @@
+use art::*;
+use ipnet::IpNet;
+
+fn route_add(top: &mut ArtDualRoot<i32>, str: &str, data: i32) {
+    let prefix: IpNet = str.parse().unwrap();
+    top.route_add(prefix, data);
+}
+
+fn route_delete(top: &mut ArtDualRoot<i32>, str: &str) {
+    let prefix: IpNet = str.parse().unwrap();
+    top.route_delete(prefix);
+}
+
+fn lookup_assert(top: &ArtDualRoot<i32>, addr: &str, route: &str) {
+    let prefix: IpNet = addr.parse().unwrap();
+    let n = top.lookup(&prefix);
+    let p: IpNet = route.parse().unwrap();
+    assert_eq!(n.unwrap().prefix(), p);
+    if prefix.prefix_len() == prefix.max_prefix_len() {
+        let n = top.lookup_addr(prefix.addr());
+        assert_eq!(n.unwrap().prefix(), p);
+    }
+}
+
+fn lookup_assert_none(top: &ArtDualRoot<i32>, addr: &str) {
+    let prefix: IpNet = addr.parse().unwrap();
+    let n = top.lookup(&prefix);
+    assert!(n.is_none());
+    assert!(top.lookup_addr(prefix.addr()).is_none());
+}
+
+#[test]
+fn dual_lookup_test() {
+    let mut top = ArtDualRoot::<i32>::new();
+
+    route_add(&mut top, "0.0.0.0/0", 4);
+    route_add(&mut top, "10.0.0.0/8", 8);
+    route_add(&mut top, "10.0.0.0/24", 24);
+    route_add(&mut top, "2001:db8::/32", 32);
+    route_add(&mut top, "2001:db8::/64", 64);
+
+    lookup_assert(&top, "10.0.0.1/32", "10.0.0.0/24");
+    lookup_assert(&top, "10.0.1.1/32", "10.0.0.0/8");
+    lookup_assert(&top, "192.168.0.1/32", "0.0.0.0/0");
+    lookup_assert(&top, "2001:db8::1/128", "2001:db8::/64");
+    lookup_assert(&top, "2001:db8:1::1/128", "2001:db8::/32");
+
+    // The IPv4 default route must not leak into the IPv6 table.
+    lookup_assert_none(&top, "2001:db9::1/128");
+    lookup_assert_none(&top, "::/0");
+
+    assert_eq!(top.iter().count(), 5);
+    assert_eq!(top.ipv4().iter().count(), 3);
+    assert_eq!(top.ipv6().iter().count(), 2);
+}
+
+#[test]
+fn dual_lookup_exact_test() {
+    let mut top = ArtDualRoot::<i32>::new();
+
+    route_add(&mut top, "10.0.0.0/8", 8);
+    route_add(&mut top, "::/8", 8);
+
+    let p: IpNet = "10.0.0.0/8".parse().unwrap();
+    assert_eq!(top.lookup_exact(&p).unwrap().data(), Some(&8));
+    let p: IpNet = "::/8".parse().unwrap();
+    assert_eq!(top.lookup_exact(&p).unwrap().prefix(), p);
+    let p: IpNet = "10.0.0.0/9".parse().unwrap();
+    assert!(top.lookup_exact(&p).is_none());
+    let p: IpNet = "0.0.0.0/8".parse().unwrap();
+    assert!(top.lookup_exact(&p).is_none());
+}
+
+#[test]
+fn dual_family_test() {
+    let mut top = ArtDualRoot::<i32>::new();
+
+    // 2001::/16 has the same leading bits as 32.1.0.0/16.
+    route_add(&mut top, "2001::/16", 16);
+    lookup_assert_none(&top, "32.1.5.5/32");
+    let p: IpNet = "32.1.0.0/16".parse().unwrap();
+    assert!(top.lookup_exact(&p).is_none());
+    assert!(top.route_delete(p).is_none());
+    assert!(top.ipv4().is_empty());
+
+    route_add(&mut top, "32.1.0.0/16", 4);
+    lookup_assert(&top, "32.1.5.5/32", "32.1.0.0/16");
+    lookup_assert(&top, "2001::1/128", "2001::/16");
+    assert_eq!(top.ipv4().len(), 1);
+    assert_eq!(top.ipv6().len(), 1);
+}
+
+#[test]
+fn dual_delete_test() {
+    let mut top = ArtDualRoot::<i32>::new();
+
+    route_add(&mut top, "0.0.0.0/0", 0);
+    route_add(&mut top, "::/0", 0);
+    assert_eq!(top.iter().count(), 2);
+
+    route_delete(&mut top, "0.0.0.0/0");
+    assert_eq!(top.iter().count(), 1);
+    lookup_assert_none(&top, "10.0.0.1/32");
+    lookup_assert(&top, "2001:db8::1/128", "::/0");
+
+    route_delete(&mut top, "::/0");
+    assert_eq!(top.iter().count(), 0);
+}
+
+#[test]
+fn dual_insert_delete_return() {
+    let mut top = ArtDualRoot::<i32>::new();
+
+    let v4: IpNet = "10.0.0.0/8".parse().unwrap();
+    let v6: IpNet = "2001:db8::/32".parse().unwrap();
+    assert!(top.route_add(v4, 4).is_none());
+    assert!(top.route_add(v6, 6).is_none());
+    assert_eq!(top.route_add(v6, 60).unwrap().data(), Some(&6));
+
+    assert_eq!(top.route_delete(v4).unwrap().data(), Some(&4));
+    assert!(top.route_delete(v4).is_none());
+    assert_eq!(top.route_delete(v6).unwrap().data(), Some(&60));
+    assert!(top.is_empty());
+}
+
+#[test]
+fn dual_len() {
+    let mut top = ArtDualRoot::<i32>::new();
+
+    route_add(&mut top, "0.0.0.0/0", 0);
+    route_add(&mut top, "10.0.0.0/8", 8);
+    route_add(&mut top, "::/0", 0);
+    route_add(&mut top, "2001:db8::/32", 32);
+    route_add(&mut top, "2001:db8::/48", 48);
+    assert_eq!(top.len(), 5);
+    assert_eq!(top.ipv4().len(), 2);
+    assert_eq!(top.ipv6().len(), 3);
+
+    route_delete(&mut top, "10.0.0.0/8");
+    assert_eq!(top.ipv4().len(), 1);
+
+    top.clear();
+    assert!(top.is_empty());
+    assert!(top.ipv6().is_empty());
+    lookup_assert_none(&top, "2001:db8::1/128");
+}
+
+#[test]
+fn dual_collect() {
+    let routes = ["10.0.0.0/8", "2001:db8::/32", "0.0.0.0/0", "::/0"];
+    let top: ArtDualRoot<i32> = routes
+        .iter()
+        .enumerate()
+        .map(|(i, route)| (route.parse().unwrap(), i as i32))
+        .collect();
+    assert_eq!(top.ipv4().len(), 2);
+    assert_eq!(top.ipv6().len(), 2);
+    lookup_assert(&top, "2001:db8::1/128", "2001:db8::/32");
+
+    let routes: Vec<(IpNet, i32)> = top.into_iter().collect();
+    assert_eq!(routes.len(), 4);
+    assert!(routes[..2]
+        .iter()
+        .all(|(prefix, _)| prefix.addr().is_ipv4()));
+    assert!(routes.contains(&("::/0".parse().unwrap(), 3)));
+
+    // Routes without data are kept in a round trip.
+    let mut top: ArtDualRoot<i32> = routes.into_iter().collect();
+    let prefix: IpNet = "2001:db8:1::/48".parse().unwrap();
+    top.insert(&ArtDualNode::new(&prefix, None));
+    let top: ArtDualRoot<i32> = top.into_routes().collect();
+    assert_eq!(top.len(), 5);
+    assert_eq!(top.lookup_exact(&prefix).unwrap().data(), None);
+}
@@ -0,0 +1,104 @@
+use art::*;
+use ipnet::{Ipv4Net, Ipv6Net};
+
+#[test]
+fn error_layout() {
+    let table = ArtRoot::<Ipv4Net, u32>::new(4, [8u8; 4].to_vec(), 32);
+    assert!(table.is_ok());
+
+    let table = ArtRoot::<Ipv4Net, u32>::new(3, [8u8; 4].to_vec(), 32);
+    assert_eq!(
+        table.err(),
+        Some(ArtError::LevelMismatch {
+            levels: 3,
+            strides: 4
+        })
+    );
+
+    let table = ArtRoot::<Ipv4Net, u32>::new(4, [8u8, 8u8, 8u8, 4u8].to_vec(), 32);
+    assert_eq!(
+        table.err(),
+        Some(ArtError::LengthMismatch {
+            total: 28,
+            alen: 32
+        })
+    );
+
+    let table = ArtRoot::<Ipv4Net, u32>::new(3, [16u8, 0u8, 16u8].to_vec(), 32);
+    assert_eq!(
+        table.err(),
+        Some(ArtError::InvalidStride { level: 1, bits: 0 })
+    );
+
+    let table = ArtRoot::<Ipv4Net, u32>::new(2, [ART_MAX_STRIDE + 1, 7].to_vec(), 32);
+    assert_eq!(
+        table.err(),
+        Some(ArtError::InvalidStride {
+            level: 0,
+            bits: ART_MAX_STRIDE + 1
+        })
+    );
+
+    let table = ArtArenaRoot::<Ipv6Net, u32>::new(31, [4u8; 32].to_vec(), 128);
+    assert!(matches!(table, Err(ArtError::LevelMismatch { .. })));
+}
+
+#[test]
+fn error_layout_alen() {
+    // A layout must cover the whole address of the prefixes.
+    let mismatch = Some(ArtError::LengthMismatch {
+        total: 32,
+        alen: 128,
+    });
+    let table = ArtRoot::<Ipv6Net, u32>::with_layout(&ArtLayout::ipv4_16_8_8());
+    assert_eq!(table.err(), mismatch);
+    let table = ArtArenaRoot::<Ipv6Net, u32>::with_layout(&ArtLayout::ipv4_16_8_8());
+    assert_eq!(table.err(), mismatch);
+
+    let mismatch = Some(ArtError::LengthMismatch {
+        total: 128,
+        alen: 32,
+    });
+    let table = ArtRoot::<Ipv4Net, u32>::with_layout(&ArtLayout::ipv6_4x32());
+    assert_eq!(table.err(), mismatch);
+    let table = ArtArenaRoot::<Ipv4Net, u32>::with_layout(&ArtLayout::ipv6_4x32());
+    assert_eq!(table.err(), mismatch);
+
+    // Raw strides may cover less than the address, never more.
+    let table = ArtRoot::<Ipv4Net, u32>::new(8, [8u8; 8].to_vec(), 64);
+    assert_eq!(
+        table.err(),
+        Some(ArtError::LengthMismatch {
+            total: 64,
+            alen: 32
+        })
+    );
+    assert!(ArtRoot::<Ipv6Net, u32>::new(4, [8u8; 4].to_vec(), 32).is_ok());
+}
+
+#[test]
+fn error_prefix_too_long() {
+    // Only the first 64 bits of the addresses are looked at.
+    let mut top = ArtRoot::<Ipv6Net, u32>::new(4, [16u8; 4].to_vec(), 64).unwrap();
+
+    let prefix: Ipv6Net = "2001:db8::/32".parse().unwrap();
+    let node = ArtNode::new(&prefix, Some(32));
+    assert!(top.try_insert(&node, &prefix).unwrap().is_none());
+
+    let prefix: Ipv6Net = "2001:db8::/96".parse().unwrap();
+    let node = ArtNode::new(&prefix, Some(96));
+    let err = top.try_insert(&node, &prefix).err().unwrap();
+    assert_eq!(err, ArtError::PrefixTooLong { plen: 96, alen: 64 });
+    assert_eq!(
+        err.to_string(),
+        "prefix length 96 exceeds address length 64"
+    );
+    assert!(top.try_lookup(&prefix).is_err());
+    assert!(top.try_delete(&prefix).is_err());
+    assert_eq!(top.iter().count(), 1);
+
+    let prefix: Ipv6Net = "2001:db8::/64".parse().unwrap();
+    let node = top.try_lookup(&prefix).unwrap().unwrap();
+    assert_eq!(node.data, Some(32));
+    assert!(top.try_delete(&prefix).unwrap().is_none());
+}
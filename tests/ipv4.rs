@@ -1,5 +1,6 @@
 use art::*;
 use ipnet::Ipv4Net;
+use std::sync::Arc;
 
 fn lookup_assert(top: &ArtRoot<Ipv4Net, i32>, addr: &str, route: &str) {
     let prefix: Ipv4Net = addr.parse().unwrap();
@@ -7,6 +8,10 @@ fn lookup_assert(top: &ArtRoot<Ipv4Net, i32>, addr: &str, route: &str) {
     // let n = top.route_ipv4_lookup(addr);
     let p: Ipv4Net = route.parse().unwrap();
     assert_eq!(n.unwrap().prefix, p);
+    if prefix.prefix_len() == 32 {
+        let n = top.lookup_addr(prefix.addr());
+        assert_eq!(n.unwrap().prefix, p);
+    }
 }
 
 fn lookup_assert_none(top: &ArtRoot<Ipv4Net, i32>, addr: &str) {
@@ -14,6 +19,7 @@ fn lookup_assert_none(top: &ArtRoot<Ipv4Net, i32>, addr: &str) {
     let n = top.lookup(&prefix);
     // let n = top.route_ipv4_lookup(addr);
     assert!(n.is_none());
+    assert!(top.lookup_addr(prefix.addr()).is_none());
 }
 
 fn lookup_test(top: &ArtRoot<Ipv4Net, i32>) {
@@ -54,7 +60,7 @@ fn ipv4_lookup_reverse_test() {
     route_ipv4_add(&mut top, "10.0.0.0/29", 29);
     route_ipv4_add(&mut top, "10.0.0.0/28", 28);
 
-    lookup_test(&mut top);
+    lookup_test(&top);
 }
 
 #[test]
@@ -200,3 +206,493 @@ fn ipv4_delete_table_default() {
     route_ipv4_delete(&mut top, "0.0.0.0/4");
     assert_eq!(top.iter().count(), 1);
 }
+
+#[test]
+fn ipv4_insert_delete_return() {
+    let mut top = ArtRoot::<Ipv4Net, i32>::new_ipv4_table();
+
+    for (route, data) in [("0.0.0.0/0", 0), ("10.0.0.0/8", 8), ("10.0.0.0/20", 20)] {
+        let prefix: Ipv4Net = route.parse().unwrap();
+        assert!(top.route_ipv4_add(prefix, data).is_none());
+
+        let prev = top.route_ipv4_add(prefix, data + 100).unwrap();
+        assert_eq!(prev.prefix, prefix);
+        assert_eq!(prev.data, Some(data));
+    }
+
+    // A more specific route does not displace the covering one.
+    let prefix: Ipv4Net = "10.0.0.0/9".parse().unwrap();
+    assert!(top.route_ipv4_add(prefix, 9).is_none());
+    let prefix: Ipv4Net = "10.0.0.0/24".parse().unwrap();
+    assert!(top.route_ipv4_add(prefix, 24).is_none());
+    assert_eq!(top.iter().count(), 5);
+
+    // Deleting an absent prefix returns nothing and removes nothing.
+    let prefix: Ipv4Net = "10.0.0.0/10".parse().unwrap();
+    assert!(top.route_ipv4_delete(prefix).is_none());
+    let prefix: Ipv4Net = "10.0.0.0/28".parse().unwrap();
+    assert!(top.route_ipv4_delete(prefix).is_none());
+    assert_eq!(top.iter().count(), 5);
+
+    for (route, data) in [
+        ("10.0.0.0/8", 108),
+        ("0.0.0.0/0", 100),
+        ("10.0.0.0/20", 120),
+    ] {
+        let prefix: Ipv4Net = route.parse().unwrap();
+        let node = top.route_ipv4_delete(prefix).unwrap();
+        assert_eq!(node.prefix, prefix);
+        assert_eq!(node.data, Some(data));
+        assert!(top.route_ipv4_delete(prefix).is_none());
+    }
+    assert_eq!(top.iter().count(), 2);
+}
+
+#[test]
+fn ipv4_iter_sorted() {
+    let mut top = ArtRoot::<Ipv4Net, i32>::new_ipv4_table();
+
+    let routes = [
+        "0.0.0.0/0",
+        "0.0.0.0/1",
+        "10.0.0.0/8",
+        "10.0.0.0/24",
+        "10.0.0.0/32",
+        "10.0.0.1/32",
+        "10.0.1.0/24",
+        "10.128.0.0/9",
+        "11.0.0.0/8",
+        "128.0.0.0/1",
+        "192.168.0.0/16",
+        "192.168.1.0/24",
+        "255.255.255.255/32",
+    ];
+    for (data, route) in routes.iter().enumerate().rev() {
+        route_ipv4_add(&mut top, route, data as i32);
+    }
+
+    let sorted: Vec<String> = top
+        .iter_sorted()
+        .map(|node| node.prefix.to_string())
+        .collect();
+    assert_eq!(sorted, routes);
+}
+
+#[test]
+fn ipv4_iter_more_specifics() {
+    let mut top = ArtRoot::<Ipv4Net, i32>::new_ipv4_table();
+
+    for route in [
+        "0.0.0.0/0",
+        "10.0.0.0/8",
+        "10.0.0.0/16",
+        "10.0.0.0/24",
+        "10.0.0.1/32",
+        "10.1.0.0/16",
+        "10.128.0.0/9",
+        "11.0.0.0/8",
+    ] {
+        route_ipv4_add(&mut top, route, 0);
+    }
+
+    let more_specifics = |top: &ArtRoot<Ipv4Net, i32>, route: &str| -> Vec<String> {
+        let prefix: Ipv4Net = route.parse().unwrap();
+        top.iter_more_specifics(&prefix)
+            .map(|node| node.prefix.to_string())
+            .collect()
+    };
+
+    assert_eq!(
+        more_specifics(&top, "10.0.0.0/8"),
+        [
+            "10.0.0.0/16",
+            "10.0.0.0/24",
+            "10.0.0.1/32",
+            "10.1.0.0/16",
+            "10.128.0.0/9"
+        ]
+    );
+    assert_eq!(
+        more_specifics(&top, "10.0.0.0/15"),
+        ["10.0.0.0/16", "10.0.0.0/24", "10.0.0.1/32", "10.1.0.0/16"]
+    );
+    assert_eq!(more_specifics(&top, "10.0.0.0/24"), ["10.0.0.1/32"]);
+    assert!(more_specifics(&top, "10.0.0.1/32").is_empty());
+    assert!(more_specifics(&top, "10.0.1.0/24").is_empty());
+    assert!(more_specifics(&top, "12.0.0.0/8").is_empty());
+    assert_eq!(more_specifics(&top, "0.0.0.0/0").len(), 7);
+
+    let prefix: Ipv4Net = "10.0.0.0/16".parse().unwrap();
+    let inclusive: Vec<String> = top
+        .iter_more_specifics_inclusive(&prefix)
+        .map(|node| node.prefix.to_string())
+        .collect();
+    assert_eq!(inclusive, ["10.0.0.0/16", "10.0.0.0/24", "10.0.0.1/32"]);
+
+    let prefix: Ipv4Net = "10.0.0.0/12".parse().unwrap();
+    assert_eq!(top.iter_more_specifics_inclusive(&prefix).count(), 4);
+}
+
+#[test]
+fn ipv4_iter_less_specifics() {
+    let mut top = ArtRoot::<Ipv4Net, i32>::new_ipv4_table();
+
+    for route in [
+        "0.0.0.0/0",
+        "10.0.0.0/8",
+        "10.0.0.0/12",
+        "10.0.0.0/16",
+        "10.0.0.0/24",
+        "10.0.0.1/32",
+        "10.1.0.0/16",
+        "11.0.0.0/8",
+    ] {
+        route_ipv4_add(&mut top, route, 0);
+    }
+
+    let less_specifics = |top: &ArtRoot<Ipv4Net, i32>, route: &str| -> Vec<String> {
+        let prefix: Ipv4Net = route.parse().unwrap();
+        top.iter_less_specifics(&prefix)
+            .map(|node| node.prefix.to_string())
+            .collect()
+    };
+
+    assert_eq!(
+        less_specifics(&top, "10.0.0.1/32"),
+        [
+            "0.0.0.0/0",
+            "10.0.0.0/8",
+            "10.0.0.0/12",
+            "10.0.0.0/16",
+            "10.0.0.0/24",
+            "10.0.0.1/32"
+        ]
+    );
+    assert_eq!(
+        less_specifics(&top, "10.0.0.2/32"),
+        [
+            "0.0.0.0/0",
+            "10.0.0.0/8",
+            "10.0.0.0/12",
+            "10.0.0.0/16",
+            "10.0.0.0/24"
+        ]
+    );
+    assert_eq!(
+        less_specifics(&top, "10.1.2.0/24"),
+        ["0.0.0.0/0", "10.0.0.0/8", "10.0.0.0/12", "10.1.0.0/16"]
+    );
+    assert_eq!(
+        less_specifics(&top, "10.0.0.0/8"),
+        ["0.0.0.0/0", "10.0.0.0/8"]
+    );
+    assert_eq!(less_specifics(&top, "12.0.0.0/8"), ["0.0.0.0/0"]);
+
+    route_ipv4_delete(&mut top, "0.0.0.0/0");
+    route_ipv4_delete(&mut top, "10.0.0.0/12");
+    assert_eq!(
+        less_specifics(&top, "10.0.0.1/32"),
+        ["10.0.0.0/8", "10.0.0.0/16", "10.0.0.0/24", "10.0.0.1/32"]
+    );
+    assert!(less_specifics(&top, "12.0.0.0/8").is_empty());
+}
+
+#[test]
+fn ipv4_get_mut() {
+    let mut top = ArtRoot::<Ipv4Net, i32>::new_ipv4_table();
+
+    route_ipv4_add(&mut top, "0.0.0.0/0", 0);
+    route_ipv4_add(&mut top, "10.0.0.0/8", 8);
+    route_ipv4_add(&mut top, "10.0.0.0/24", 24);
+
+    let prefix: Ipv4Net = "10.0.0.0/8".parse().unwrap();
+    let addr = Arc::as_ptr(&top.lookup_exact(&prefix).unwrap()) as usize;
+    *top.get_mut(&prefix).unwrap() += 100;
+
+    // The route is modified in place and every index it is alloted to
+    // sees the change.
+    assert_eq!(
+        Arc::as_ptr(&top.lookup_exact(&prefix).unwrap()) as usize,
+        addr
+    );
+    lookup_assert(&top, "10.1.0.1/32", "10.0.0.0/8");
+    assert_eq!(
+        top.lookup(&"10.1.0.1/32".parse().unwrap()).unwrap().data,
+        Some(108)
+    );
+    assert_eq!(
+        top.lookup(&"10.255.0.0/16".parse().unwrap()).unwrap().data,
+        Some(108)
+    );
+
+    // A route held elsewhere is copied before being modified.
+    let held = top.lookup_exact(&prefix).unwrap();
+    *top.lookup_exact_mut(&prefix).unwrap() += 100;
+    assert_eq!(held.data, Some(108));
+    assert_eq!(
+        top.lookup(&"10.1.0.1/32".parse().unwrap()).unwrap().data,
+        Some(208)
+    );
+
+    assert!(top.get_mut(&"10.0.0.0/9".parse().unwrap()).is_none());
+    assert!(top.get_mut(&"10.0.0.0/25".parse().unwrap()).is_none());
+
+    for (_, mut data) in top.iter_mut() {
+        *data = -*data;
+    }
+    let mut routes: Vec<(String, i32)> = top
+        .iter()
+        .map(|node| (node.prefix.to_string(), node.data.unwrap()))
+        .collect();
+    routes.sort();
+    assert_eq!(
+        routes,
+        [
+            ("0.0.0.0/0".to_string(), 0),
+            ("10.0.0.0/24".to_string(), -24),
+            ("10.0.0.0/8".to_string(), -208)
+        ]
+    );
+    lookup_assert(&top, "10.0.0.1/32", "10.0.0.0/24");
+    assert_eq!(
+        top.lookup(&"10.0.0.1/32".parse().unwrap()).unwrap().data,
+        Some(-24)
+    );
+}
+
+#[test]
+fn ipv4_entry() {
+    let mut top = ArtRoot::<Ipv4Net, i32>::new_ipv4_table();
+    let prefix: Ipv4Net = "10.0.0.0/24".parse().unwrap();
+
+    // Count the updates of a prefix.
+    for _ in 0..3 {
+        *top.entry(prefix)
+            .unwrap()
+            .and_modify(|n| *n += 1)
+            .or_insert(1) *= 10;
+    }
+    assert_eq!(top.lookup_exact(&prefix).unwrap().data, Some(1110));
+    lookup_assert(&top, "10.0.0.1/32", "10.0.0.0/24");
+
+    route_ipv4_add(&mut top, "10.0.0.0/8", 8);
+    match top.entry("10.0.0.0/8".parse().unwrap()).unwrap() {
+        ArtRouteEntry::Occupied(mut entry) => {
+            assert_eq!(entry.get(), Some(&8));
+            assert_eq!(entry.insert(9), Some(8));
+            assert_eq!(entry.into_mut().as_deref(), Some(&9));
+        }
+        ArtRouteEntry::Vacant(_) => panic!("10.0.0.0/8 is in the table"),
+    }
+
+    // A less specific route alloted to the index is not the entry.
+    match top.entry("10.0.0.0/16".parse().unwrap()).unwrap() {
+        ArtRouteEntry::Occupied(_) => panic!("10.0.0.0/16 is not in the table"),
+        ArtRouteEntry::Vacant(entry) => {
+            assert_eq!(entry.key().to_string(), "10.0.0.0/16");
+            *entry.insert(16) += 1;
+        }
+    }
+    assert_eq!(top.iter().count(), 3);
+    lookup_assert(&top, "10.0.1.1/32", "10.0.0.0/16");
+    assert_eq!(
+        top.lookup(&"10.0.1.1/32".parse().unwrap()).unwrap().data,
+        Some(17)
+    );
+
+    // Removing through the entry reclaims the tables left empty.
+    for route in ["10.0.0.0/24", "10.0.0.0/16", "10.0.0.0/8"] {
+        let ArtRouteEntry::Occupied(entry) = top.entry(route.parse().unwrap()).unwrap() else {
+            panic!("{} is in the table", route);
+        };
+        assert_eq!(entry.remove().prefix.to_string(), route);
+    }
+    assert_eq!(top.iter().count(), 0);
+    let root = top.root();
+    assert!((1..512).all(|i| !matches!(root.get_entry(i), ArtEntry::Table(_))));
+
+    *top.entry("0.0.0.0/0".parse().unwrap())
+        .unwrap()
+        .or_insert(0) += 1;
+    lookup_assert(&top, "10.0.1.1/32", "0.0.0.0/0");
+    assert_eq!(
+        top.lookup(&"10.0.1.1/32".parse().unwrap()).unwrap().data,
+        Some(1)
+    );
+}
+
+#[test]
+fn ipv4_len() {
+    let mut top = ArtRoot::<Ipv4Net, i32>::new_ipv4_table();
+    assert!(top.is_empty());
+
+    route_ipv4_add(&mut top, "0.0.0.0/0", 0);
+    route_ipv4_add(&mut top, "10.0.0.0/8", 8);
+    route_ipv4_add(&mut top, "10.1.0.0/16", 16);
+    route_ipv4_add(&mut top, "10.1.1.0/24", 24);
+    assert_eq!(top.len(), 4);
+
+    // Replacing a route or deleting a missing one keeps the count.
+    route_ipv4_add(&mut top, "10.1.0.0/16", 17);
+    route_ipv4_delete(&mut top, "10.1.0.0/17");
+    let prefix: Ipv4Net = "10.0.0.0/8".parse().unwrap();
+    let node = top.lookup_exact(&prefix).unwrap();
+    assert!(top.insert(&node, &prefix).is_some());
+    assert_eq!(top.len(), 4);
+
+    top.entry("10.2.0.0/16".parse().unwrap())
+        .unwrap()
+        .or_insert(16);
+    top.entry("10.1.0.0/16".parse().unwrap())
+        .unwrap()
+        .or_insert(16);
+    assert_eq!(top.len(), 5);
+    if let ArtRouteEntry::Occupied(entry) = top.entry("10.1.1.0/24".parse().unwrap()).unwrap() {
+        entry.remove();
+    }
+    route_ipv4_delete(&mut top, "0.0.0.0/0");
+    assert_eq!(top.len(), 3);
+    assert_eq!(top.len(), top.iter().count());
+
+    top.clear();
+    assert!(top.is_empty());
+    assert_eq!(top.iter().count(), 0);
+    assert!(top.lookup(&"10.1.1.1/32".parse().unwrap()).is_none());
+    let root = top.root();
+    assert!((1..512).all(|i| matches!(root.get_entry(i), ArtEntry::None)));
+
+    route_ipv4_add(&mut top, "10.1.1.0/24", 24);
+    assert_eq!(top.len(), 1);
+    lookup_assert(&top, "10.1.1.1/32", "10.1.1.0/24");
+}
+
+#[test]
+fn ipv4_collect() {
+    let routes = [
+        ("10.0.0.0/8", 8),
+        ("10.1.0.0/16", 16),
+        ("10.1.1.0/24", 24),
+        ("0.0.0.0/0", 0),
+        ("10.1.0.0/16", 17),
+    ];
+    let mut top: ArtRoot<Ipv4Net, i32> = routes
+        .iter()
+        .map(|(route, data)| (route.parse().unwrap(), *data))
+        .collect();
+    assert_eq!(top.len(), 4);
+    lookup_assert(&top, "10.1.2.1/32", "10.1.0.0/16");
+    assert_eq!(
+        top.lookup_exact(&"10.1.0.0/16".parse().unwrap())
+            .unwrap()
+            .data,
+        Some(17)
+    );
+
+    top.extend([("10.1.1.1/32".parse().unwrap(), 32)]);
+    assert_eq!(top.len(), 5);
+    lookup_assert(&top, "10.1.1.1/32", "10.1.1.1/32");
+
+    // A route still referenced elsewhere is copied, not moved out.
+    let held = top.lookup_exact(&"10.0.0.0/8".parse().unwrap()).unwrap();
+    route_ipv4_add(&mut top, "192.168.0.0/16", -1);
+    let mut routes: Vec<(String, i32)> = top
+        .into_iter()
+        .filter(|(_, data)| *data >= 0)
+        .map(|(prefix, data)| (prefix.to_string(), data))
+        .collect();
+    routes.sort();
+    assert_eq!(
+        routes,
+        [
+            ("0.0.0.0/0".to_string(), 0),
+            ("10.0.0.0/8".to_string(), 8),
+            ("10.1.0.0/16".to_string(), 17),
+            ("10.1.1.0/24".to_string(), 24),
+            ("10.1.1.1/32".to_string(), 32),
+        ]
+    );
+    assert_eq!(held.data, Some(8));
+}
+
+#[test]
+fn ipv4_collect_without_data() {
+    let mut top = ArtRoot::<Ipv4Net, i32>::new_ipv4_table();
+    route_ipv4_add(&mut top, "10.0.0.0/8", 8);
+    for route in ["10.1.0.0/16", "192.168.0.0/16"] {
+        let prefix: Ipv4Net = route.parse().unwrap();
+        top.insert(&ArtNode::new(&prefix, None), &prefix);
+    }
+    assert_eq!(top.len(), 3);
+
+    // Routes without data are skipped unless asked for.
+    assert_eq!(top.iter_mut().count(), 1);
+    let data: Vec<Option<i32>> = top
+        .iter_mut_all()
+        .map(|(_, data)| data.as_deref().copied())
+        .collect();
+    assert_eq!(data.len(), 3);
+    assert_eq!(data.iter().filter(|data| data.is_none()).count(), 2);
+
+    // Routes without data make it through a round trip.
+    let routes: Vec<(Ipv4Net, Option<i32>)> = top.into_routes().collect();
+    assert_eq!(routes.len(), 3);
+    let top: ArtRoot<Ipv4Net, i32> = routes.into_iter().collect();
+    assert_eq!(top.len(), 3);
+    let node = top.lookup_exact(&"10.1.0.0/16".parse().unwrap()).unwrap();
+    assert_eq!(node.data, None);
+    let node = top.lookup_exact(&"10.0.0.0/8".parse().unwrap()).unwrap();
+    assert_eq!(node.data, Some(8));
+
+    let routes: Vec<(Ipv4Net, i32)> = top.into_iter().collect();
+    assert_eq!(routes, [("10.0.0.0/8".parse().unwrap(), 8)]);
+}
+
+#[test]
+fn ipv4_drain_filter() {
+    let mut top = ArtRoot::<Ipv4Net, i32>::new_ipv4_table();
+
+    // Routes learnt from two peers, 1 and 2.
+    route_ipv4_add(&mut top, "0.0.0.0/0", 1);
+    route_ipv4_add(&mut top, "10.0.0.0/8", 2);
+    route_ipv4_add(&mut top, "10.0.0.0/9", 1);
+    route_ipv4_add(&mut top, "10.1.0.0/16", 1);
+    route_ipv4_add(&mut top, "10.1.1.0/24", 2);
+    route_ipv4_add(&mut top, "10.1.1.128/25", 1);
+    route_ipv4_add(&mut top, "192.168.0.0/16", 1);
+    route_ipv4_add(&mut top, "192.168.1.0/24", 1);
+
+    let mut removed: Vec<String> = top
+        .drain_filter(|_, data| data == Some(&1))
+        .iter()
+        .map(|node| node.prefix.to_string())
+        .collect();
+    removed.sort();
+    assert_eq!(
+        removed,
+        [
+            "0.0.0.0/0",
+            "10.0.0.0/9",
+            "10.1.0.0/16",
+            "10.1.1.128/25",
+            "192.168.0.0/16",
+            "192.168.1.0/24"
+        ]
+    );
+    assert_eq!(top.len(), 2);
+
+    // The indices alloted to the removed routes fall back to the routes of
+    // the other peer.
+    lookup_assert(&top, "10.1.0.1/32", "10.0.0.0/8");
+    lookup_assert(&top, "10.2.0.1/32", "10.0.0.0/8");
+    lookup_assert(&top, "10.1.1.129/32", "10.1.1.0/24");
+    assert!(top.lookup(&"192.168.1.1/32".parse().unwrap()).is_none());
+    assert!(top.lookup(&"11.0.0.1/32".parse().unwrap()).is_none());
+
+    top.retain(|prefix, _| prefix.prefix_len() < 24);
+    assert_eq!(top.len(), 1);
+    lookup_assert(&top, "10.1.1.129/32", "10.0.0.0/8");
+    top.retain(|_, _| false);
+    assert!(top.is_empty());
+    let root = top.root();
+    assert!((1..512).all(|i| matches!(root.get_entry(i), ArtEntry::None)));
+}
@@ -1,12 +1,115 @@
-//use art::*;
+use art::*;
+use ipnet::Ipv6Net;
+
+fn lookup_assert(top: &ArtRoot<Ipv6Net, i32>, addr: &str, route: &str) {
+    let prefix: Ipv6Net = addr.parse().unwrap();
+    let n = top.lookup(&prefix);
+    let p: Ipv6Net = route.parse().unwrap();
+    assert_eq!(n.unwrap().prefix, p);
+    if prefix.prefix_len() == 128 {
+        let n = top.lookup_addr(prefix.addr());
+        assert_eq!(n.unwrap().prefix, p);
+    }
+}
+
+fn lookup_assert_none(top: &ArtRoot<Ipv6Net, i32>, addr: &str) {
+    let prefix: Ipv6Net = addr.parse().unwrap();
+    let n = top.lookup(&prefix);
+    assert!(n.is_none());
+    assert!(top.lookup_addr(prefix.addr()).is_none());
+}
+
+fn lookup_test(top: &ArtRoot<Ipv6Net, i32>) {
+    lookup_assert(top, "2001:db8::/128", "2001:db8::/128");
+    lookup_assert(top, "2001:db8::1/128", "2001:db8::/127");
+    lookup_assert(top, "2001:db8::2/128", "2001:db8::/126");
+    lookup_assert(top, "2001:db8::3/128", "2001:db8::/126");
+
+    lookup_assert(top, "2001:db8::4/128", "2001:db8::/125");
+    lookup_assert(top, "2001:db8::7/128", "2001:db8::/125");
+    lookup_assert(top, "2001:db8::8/128", "2001:db8::/124");
+    lookup_assert(top, "2001:db8::f/128", "2001:db8::/124");
+    lookup_assert(top, "2001:db8::/124", "2001:db8::/124");
+
+    lookup_assert_none(top, "2001:db8::10/128");
+    lookup_assert_none(top, "2001:db8::ff/128");
+    lookup_assert_none(top, "::/0");
+}
+
+fn route_ipv6_add(top: &mut ArtRoot<Ipv6Net, i32>, str: &str, data: i32) {
+    let prefix: Ipv6Net = str.parse().unwrap();
+    top.route_ipv6_add(prefix, data);
+}
+
+fn route_ipv6_delete(top: &mut ArtRoot<Ipv6Net, i32>, str: &str) {
+    let prefix: Ipv6Net = str.parse().unwrap();
+    top.route_ipv6_delete(prefix);
+}
+
+#[test]
+fn ipv6_lookup_reverse_test() {
+    let mut top = ArtRoot::<Ipv6Net, i32>::new_ipv6_table();
+
+    // 2001:db8::/{124..128}
+    route_ipv6_add(&mut top, "2001:db8::/128", 128);
+    route_ipv6_add(&mut top, "2001:db8::/127", 127);
+    route_ipv6_add(&mut top, "2001:db8::/126", 126);
+    route_ipv6_add(&mut top, "2001:db8::/125", 125);
+    route_ipv6_add(&mut top, "2001:db8::/124", 124);
+
+    lookup_test(&top);
+}
+
+#[test]
+fn ipv6_lookup_test() {
+    let mut top = ArtRoot::<Ipv6Net, i32>::new_ipv6_table();
+
+    route_ipv6_add(&mut top, "2001:db8::/124", 124);
+    route_ipv6_add(&mut top, "2001:db8::/125", 125);
+    route_ipv6_add(&mut top, "2001:db8::/126", 126);
+    route_ipv6_add(&mut top, "2001:db8::/127", 127);
+    route_ipv6_add(&mut top, "2001:db8::/128", 128);
+
+    lookup_test(&top);
+}
+
+#[test]
+fn ipv6_lookup_less_specific() {
+    let mut top = ArtRoot::<Ipv6Net, i32>::new_ipv6_table();
+
+    route_ipv6_add(&mut top, "::/0", 0);
+    route_ipv6_add(&mut top, "2000::/3", 3);
+    route_ipv6_add(&mut top, "2001:db8::/32", 32);
+    route_ipv6_add(&mut top, "2001:db8:1::/48", 48);
+
+    lookup_assert(&top, "2001:db8:1::1/128", "2001:db8:1::/48");
+    lookup_assert(&top, "2001:db8:2::1/128", "2001:db8::/32");
+    lookup_assert(&top, "2001:db9::1/128", "2000::/3");
+    lookup_assert(&top, "fe80::1/128", "::/0");
+    assert_eq!(top.iter().count(), 4);
+}
 
 #[test]
 fn ipv6_delete_default() {
-    // let mut top = ArtRoot::new_ipv6_table();
+    let mut top = ArtRoot::<Ipv6Net, i32>::new_ipv6_table();
+
+    route_ipv6_add(&mut top, "::/0", 0);
+    assert_eq!(top.iter().count(), 1);
+
+    route_ipv6_delete(&mut top, "::/0");
+    assert_eq!(top.iter().count(), 0);
+}
+
+#[test]
+fn ipv6_delete_table_default() {
+    let mut top = ArtRoot::<Ipv6Net, i32>::new_ipv6_table();
+
+    route_ipv6_add(&mut top, "2001:db8::/32", 32);
+    assert_eq!(top.iter().count(), 1);
 
-    // top.route_ipv6_add("::/0", 0);
-    // assert_eq!(top.iter().count(), 1);
+    route_ipv6_add(&mut top, "2001:db8::/33", 33);
+    assert_eq!(top.iter().count(), 2);
 
-    // top.route_ipv6_delete("::/0");
-    // assert_eq!(top.iter().count(), 0);
+    route_ipv6_delete(&mut top, "2001:db8::/32");
+    assert_eq!(top.iter().count(), 1);
 }
@@ -0,0 +1,297 @@
+use art::*;
+use ipnet::{IpNet, Ipv4Net};
+use std::sync::Arc;
+
+fn ipv4_layouts() -> Vec<ArtLayout> {
+    vec![
+        ArtLayout::ipv4_8_4_4_4_4_4_4(),
+        ArtLayout::ipv4_8_8_8_8(),
+        ArtLayout::ipv4_16_8_8(),
+        // Strides starting in the middle of a byte.
+        ArtLayout::new([4, 16, 12].to_vec(), 32).unwrap(),
+        ArtLayout::new([3, 13, 9, 7].to_vec(), 32).unwrap(),
+    ]
+}
+
+// Layouts with sparse tables, which an arena does not take.
+fn ipv4_wide_layouts() -> Vec<ArtLayout> {
+    vec![
+        ArtLayout::new([32].to_vec(), 32).unwrap(),
+        ArtLayout::new([30, 2].to_vec(), 32).unwrap(),
+        ArtLayout::new([25, 7].to_vec(), 32).unwrap(),
+        ArtLayout::new([4, 28].to_vec(), 32).unwrap(),
+        ArtLayout::new([2, 26, 4].to_vec(), 32).unwrap(),
+    ]
+}
+
+#[test]
+fn layout_presets() {
+    let layout = ArtLayout::ipv4_16_8_8();
+    assert_eq!(layout.levels(), 3);
+    assert_eq!(layout.bits(), [16, 8, 8]);
+    assert_eq!(layout.alen(), 32);
+    assert_eq!(ArtLayout::ipv4_24_8().bits(), [24, 8]);
+
+    let layout = ArtLayout::ipv6_16_16_8x12();
+    assert_eq!(layout.levels(), 14);
+    assert_eq!(layout.bits()[..3], [16, 16, 8]);
+    assert_eq!(ArtLayout::ipv6_8x16().levels(), 16);
+    assert_eq!(Ipv4Net::default_layout(), ArtLayout::ipv4_8_4_4_4_4_4_4());
+}
+
+#[test]
+fn layout_builder() {
+    let layout = ArtLayout::builder(32).stride(8).strides(4, 6).build();
+    assert_eq!(layout, Ok(ArtLayout::ipv4_8_4_4_4_4_4_4()));
+
+    let layout = ArtLayout::builder(32).strides(8, 3).build();
+    assert_eq!(
+        layout,
+        Err(ArtError::LengthMismatch {
+            total: 24,
+            alen: 32
+        })
+    );
+    let layout = ArtLayout::builder(32)
+        .stride(16)
+        .stride(0)
+        .stride(16)
+        .build();
+    assert_eq!(layout, Err(ArtError::InvalidStride { level: 1, bits: 0 }));
+    assert!(ArtLayout::new([].to_vec(), 32).is_err());
+
+    // Strides wider than ART_DENSE_STRIDE make sparse tables.
+    assert!(ArtLayout::new([30, 2].to_vec(), 32).is_ok());
+    assert_eq!(
+        ArtLayout::builder(128)
+            .stride(ART_MAX_STRIDE + 1)
+            .stride(128 - ART_MAX_STRIDE - 1)
+            .build(),
+        Err(ArtError::InvalidStride {
+            level: 0,
+            bits: ART_MAX_STRIDE + 1
+        })
+    );
+    let layout = ArtLayout::builder(128)
+        .strides(ART_MAX_STRIDE, 2)
+        .stride(128 - 2 * ART_MAX_STRIDE)
+        .build();
+    assert_eq!(layout.map(|layout| layout.levels()), Ok(3));
+}
+
+#[test]
+fn layout_ipv4_lookup() {
+    let routes = [
+        "0.0.0.0/0",
+        "10.0.0.0/7",
+        "10.0.0.0/8",
+        "10.1.0.0/16",
+        "10.1.0.0/17",
+        "10.1.1.0/24",
+        "10.1.1.128/25",
+        "10.1.1.1/32",
+    ];
+    let addrs = [
+        ("11.0.0.1", "10.0.0.0/7"),
+        ("10.2.0.1", "10.0.0.0/8"),
+        ("10.1.128.1", "10.1.0.0/16"),
+        ("10.1.2.1", "10.1.0.0/17"),
+        ("10.1.1.2", "10.1.1.0/24"),
+        ("10.1.1.129", "10.1.1.128/25"),
+        ("10.1.1.1", "10.1.1.1/32"),
+        ("192.168.0.1", "0.0.0.0/0"),
+    ];
+
+    for layout in ipv4_wide_layouts() {
+        let mut top = ArtRoot::<Ipv4Net, i32>::with_layout(&layout).unwrap();
+        for route in routes {
+            top.route_ipv4_add(route.parse().unwrap(), 0);
+        }
+        for (addr, route) in addrs {
+            let node = top.lookup_addr(addr.parse().unwrap()).unwrap();
+            assert_eq!(node.prefix, route.parse().unwrap(), "{:?}", layout);
+        }
+        for route in routes {
+            assert!(top.route_ipv4_delete(route.parse().unwrap()).is_some());
+        }
+        assert!(top.is_empty());
+    }
+
+    for layout in ipv4_layouts() {
+        let mut top = ArtRoot::<Ipv4Net, i32>::with_layout(&layout).unwrap();
+        let mut arena = ArtArenaRoot::<Ipv4Net, i32>::with_layout(&layout).unwrap();
+        for route in routes {
+            top.route_ipv4_add(route.parse().unwrap(), 0);
+            arena.route_ipv4_add(route.parse().unwrap(), 0);
+        }
+        for (addr, route) in addrs {
+            let route: Ipv4Net = route.parse().unwrap();
+            let node = top.lookup_addr(addr.parse().unwrap()).unwrap();
+            assert_eq!(node.prefix, route, "{:?}", layout);
+            let node = arena.lookup_addr(addr.parse().unwrap()).unwrap();
+            assert_eq!(node.prefix, route, "{:?}", layout);
+        }
+
+        for route in routes {
+            assert!(top.route_ipv4_delete(route.parse().unwrap()).is_some());
+            assert!(arena.route_ipv4_delete(route.parse().unwrap()).is_some());
+        }
+        assert!(top.is_empty());
+        assert!(arena.is_empty());
+    }
+}
+
+#[test]
+fn layout_dual() {
+    let top =
+        ArtDualRoot::<i32>::with_layouts(&ArtLayout::ipv4_16_8_8(), &ArtLayout::ipv6_16_16_8x12());
+    let mut top = top.unwrap();
+    let prefix: IpNet = "2001:db8::/32".parse().unwrap();
+    top.route_add(prefix, 32);
+    let node = top.lookup_addr("2001:db8::1".parse().unwrap()).unwrap();
+    assert_eq!(node.prefix(), prefix);
+
+    let top = ArtDualRoot::<i32>::with_layouts(&ArtLayout::ipv6_4x32(), &ArtLayout::ipv6_4x32());
+    assert_eq!(
+        top.err(),
+        Some(ArtError::LengthMismatch {
+            total: 128,
+            alen: 32
+        })
+    );
+}
+
+// Routes clustered in 10.0.0.0/8 so they nest within the wide tables.
+fn random_routes(mut seed: u64, count: usize) -> Vec<Ipv4Net> {
+    (0..count)
+        .map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            let addr = 0x0a000000 | ((seed >> 32) as u32 & 0x00f0f0ff);
+            let plen = (seed % 33) as u8;
+            Ipv4Net::new(addr.into(), plen).unwrap().trunc()
+        })
+        .collect()
+}
+
+fn sorted(top: &ArtRoot<Ipv4Net, u32>) -> Vec<(Ipv4Net, Option<u32>)> {
+    top.iter_sorted()
+        .map(|node| (node.prefix, node.data))
+        .collect()
+}
+
+fn prefixes(iter: impl Iterator<Item = Arc<ArtNode<Ipv4Net, u32>>>) -> Vec<Ipv4Net> {
+    iter.map(|node| node.prefix).collect()
+}
+
+fn assert_same_lookups(
+    top: &ArtRoot<Ipv4Net, u32>,
+    expected: &ArtRoot<Ipv4Net, u32>,
+    routes: &[Ipv4Net],
+) {
+    for route in routes {
+        let addrs = [route.network(), route.broadcast()];
+        for addr in addrs {
+            let node = top.lookup_addr(addr).map(|node| node.prefix);
+            assert_eq!(node, expected.lookup_addr(addr).map(|node| node.prefix));
+        }
+        let node = top.lookup(route).map(|node| node.prefix);
+        assert_eq!(node, expected.lookup(route).map(|node| node.prefix));
+        let node = top.lookup_exact(route).map(|node| node.prefix);
+        assert_eq!(node, expected.lookup_exact(route).map(|node| node.prefix));
+        assert_eq!(
+            prefixes(top.iter_less_specifics(route)),
+            prefixes(expected.iter_less_specifics(route))
+        );
+    }
+    for route in routes.iter().step_by(10) {
+        assert_eq!(
+            prefixes(top.iter_more_specifics(route)),
+            prefixes(expected.iter_more_specifics(route))
+        );
+    }
+}
+
+#[test]
+fn layout_ipv4_wide() {
+    let routes = random_routes(0x2545f4914f6cdd1d, 1000);
+
+    for layout in ipv4_wide_layouts() {
+        assert!(matches!(
+            ArtArenaRoot::<Ipv4Net, u32>::with_layout(&layout),
+            Err(ArtError::InvalidStride { .. })
+        ));
+
+        let mut expected = ArtRoot::<Ipv4Net, u32>::new_ipv4_table();
+        let mut top = ArtRoot::<Ipv4Net, u32>::with_layout(&layout).unwrap();
+        for (i, route) in routes.iter().enumerate() {
+            let old = expected.route_ipv4_add(*route, i as u32);
+            let node = top.route_ipv4_add(*route, i as u32);
+            assert_eq!(node.map(|node| node.data), old.map(|node| node.data));
+        }
+        assert_eq!(top.len(), expected.len());
+        assert_eq!(top.iter().count(), expected.len());
+        assert_eq!(sorted(&top), sorted(&expected));
+        assert_eq!(top.diff(&expected).count(), 0);
+        assert_same_lookups(&top, &expected, &routes);
+
+        // Comparing with a snapshot only finds the deleted routes.
+        let snapshot = top.snapshot();
+        for route in routes.iter().step_by(2) {
+            let old = expected.route_ipv4_delete(*route);
+            let node = top.route_ipv4_delete(*route);
+            assert_eq!(node.map(|node| node.prefix), old.map(|node| node.prefix));
+        }
+        let changes: Vec<_> = snapshot.diff(&top).collect();
+        assert_eq!(changes.len(), snapshot.len() - top.len());
+        assert!(changes
+            .iter()
+            .all(|change| matches!(change, ArtChange::Delete(_))));
+        assert_eq!(sorted(&top), sorted(&expected));
+        assert_same_lookups(&top, &expected, &routes);
+
+        top.retain(|_, data| data.unwrap() % 3 != 0);
+        expected.retain(|_, data| data.unwrap() % 3 != 0);
+        assert_eq!(top.len(), expected.len());
+        assert_eq!(sorted(&top), sorted(&expected));
+
+        for route in routes.iter().take(200) {
+            *top.entry(*route).unwrap().or_insert(0) += 1;
+            *expected.entry(*route).unwrap().or_insert(0) += 1;
+        }
+        for (_, mut data) in top.iter_mut() {
+            *data += 1;
+        }
+        for (_, mut data) in expected.iter_mut() {
+            *data += 1;
+        }
+        assert_eq!(sorted(&top), sorted(&expected));
+        assert_same_lookups(&top, &expected, &routes);
+
+        let mut all: Vec<_> = top.into_iter().collect();
+        all.sort();
+        let mut expected: Vec<_> = expected.into_iter().collect();
+        expected.sort();
+        assert_eq!(all, expected);
+    }
+}
+
+#[test]
+fn layout_ipv4_wide_drain() {
+    let routes = random_routes(0x9e3779b97f4a7c15, 1000);
+
+    for layout in ipv4_wide_layouts() {
+        let mut top = ArtRoot::<Ipv4Net, u32>::with_layout(&layout).unwrap();
+        top.extend(routes.iter().copied().zip(0..));
+        let len = top.len();
+        let removed = top.drain_filter(|prefix, _| prefix.prefix_len() > 20);
+        assert_eq!(removed.len() + top.len(), len);
+        assert!(top.iter().all(|node| node.prefix.prefix_len() <= 20));
+
+        top.retain(|_, _| false);
+        assert!(top.is_empty());
+        assert_eq!(top.iter().count(), 0);
+        assert!(top.lookup_addr("10.0.0.1".parse().unwrap()).is_none());
+    }
+}
@@ -0,0 +1,260 @@
+use art::*;
+use ipnet::Ipv4Net;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+type Log = Arc<Mutex<Vec<String>>>;
+
+fn describe(change: &ArtChange<Ipv4Net, u32>) -> String {
+    match change {
+        ArtChange::Add(node) => format!("add {} {:?}", node.prefix, node.data),
+        ArtChange::Replace { old, new } => {
+            format!("replace {} {:?} {:?}", new.prefix, old.data, new.data)
+        }
+        ArtChange::Delete(node) => format!("delete {} {:?}", node.prefix, node.data),
+    }
+}
+
+fn observe(top: &mut ArtRoot<Ipv4Net, u32>) -> (Log, usize) {
+    let log = Log::default();
+    let changes = log.clone();
+    let id = top.subscribe(move |change| changes.lock().unwrap().push(describe(change)));
+    (log, id)
+}
+
+fn take(log: &Log) -> Vec<String> {
+    std::mem::take(&mut *log.lock().unwrap())
+}
+
+fn prefix(s: &str) -> Ipv4Net {
+    s.parse().unwrap()
+}
+
+#[test]
+fn observer_insert_delete() {
+    let mut top = ArtRoot::<Ipv4Net, u32>::new_ipv4_table();
+    let (log, id) = observe(&mut top);
+
+    top.route_ipv4_add(prefix("10.0.0.0/8"), 8);
+    top.route_ipv4_add(prefix("10.1.0.0/16"), 16);
+    top.route_ipv4_add(prefix("10.1.0.0/16"), 17);
+    assert_eq!(
+        take(&log),
+        [
+            "add 10.0.0.0/8 Some(8)",
+            "add 10.1.0.0/16 Some(16)",
+            "replace 10.1.0.0/16 Some(16) Some(17)"
+        ]
+    );
+
+    // Re-inserting the same route or the same data is not a change.
+    let node = top.lookup_exact(&prefix("10.0.0.0/8")).unwrap();
+    top.insert(&node, &prefix("10.0.0.0/8"));
+    top.route_ipv4_add(prefix("10.0.0.0/8"), 8);
+    assert!(take(&log).is_empty());
+
+    // Neither is deleting a route that is not in the table.
+    assert!(top.route_ipv4_delete(prefix("10.0.0.0/9")).is_none());
+    top.route_ipv4_delete(prefix("10.0.0.0/8"));
+    assert_eq!(take(&log), ["delete 10.0.0.0/8 Some(8)"]);
+
+    assert!(top.unsubscribe(id));
+    assert!(!top.unsubscribe(id));
+    top.route_ipv4_delete(prefix("10.1.0.0/16"));
+    assert!(take(&log).is_empty());
+}
+
+#[test]
+fn observer_entry() {
+    let mut top = ArtRoot::<Ipv4Net, u32>::new_ipv4_table();
+    let (log, _) = observe(&mut top);
+
+    *top.entry(prefix("10.0.0.0/8")).unwrap().or_insert(8) += 1;
+    top.entry(prefix("10.0.0.0/8")).unwrap().or_insert(0);
+    if let ArtRouteEntry::Occupied(mut entry) = top.entry(prefix("10.0.0.0/8")).unwrap() {
+        assert_eq!(entry.insert(10), Some(9));
+        assert_eq!(entry.insert(10), Some(10));
+        entry.remove();
+    }
+    assert_eq!(
+        take(&log),
+        [
+            "add 10.0.0.0/8 Some(8)",
+            "replace 10.0.0.0/8 Some(8) Some(9)",
+            "replace 10.0.0.0/8 Some(9) Some(10)",
+            "delete 10.0.0.0/8 Some(10)"
+        ]
+    );
+
+    // A route without data gets the data given to or_insert().
+    let node = ArtNode::new(&prefix("10.1.0.0/16"), None);
+    top.insert(&node, &prefix("10.1.0.0/16"));
+    top.entry(prefix("10.1.0.0/16")).unwrap().or_insert(16);
+    assert_eq!(
+        take(&log),
+        ["add 10.1.0.0/16 None", "replace 10.1.0.0/16 None Some(16)"]
+    );
+}
+
+#[test]
+fn observer_mirror() {
+    let mut top = ArtRoot::<Ipv4Net, u32>::new_ipv4_table();
+    let mirror = Arc::new(Mutex::new(BTreeMap::new()));
+    let changes = mirror.clone();
+    top.subscribe(move |change: &ArtChange<Ipv4Net, u32>| {
+        let mut mirror = changes.lock().unwrap();
+        match change {
+            ArtChange::Add(node) | ArtChange::Replace { new: node, .. } => {
+                mirror.insert(node.prefix, node.data);
+            }
+            ArtChange::Delete(node) => {
+                mirror.remove(&node.prefix);
+            }
+        }
+    });
+    let check = |top: &ArtRoot<Ipv4Net, u32>| {
+        let routes: BTreeMap<_, _> = top.iter().map(|node| (node.prefix, node.data)).collect();
+        assert_eq!(*mirror.lock().unwrap(), routes);
+    };
+
+    for _ in 0..3 {
+        *top.entry(prefix("10.0.0.0/8"))
+            .unwrap()
+            .and_modify(|n| *n += 1)
+            .or_insert(1) *= 10;
+        check(&top);
+    }
+    assert_eq!(top.get_mut(&prefix("10.0.0.0/8")).as_deref(), Some(&1110));
+
+    top.entry(prefix("10.1.0.0/16"))
+        .unwrap()
+        .or_insert_with(|| 16);
+    if let ArtRouteEntry::Occupied(mut entry) = top.entry(prefix("10.1.0.0/16")).unwrap() {
+        *entry.get_mut().unwrap() += 1;
+        *entry.into_mut().unwrap() += 1;
+    }
+    check(&top);
+
+    // The route is held by a snapshot, so it is copied when modified.
+    let snapshot = top.snapshot();
+    *top.lookup_exact_mut(&prefix("10.1.0.0/16")).unwrap() = 0;
+    for (_, mut data) in top.iter_mut() {
+        *data += 1;
+    }
+    check(&top);
+    assert_eq!(top.get_mut(&prefix("10.1.0.0/16")).as_deref(), Some(&1));
+    assert_eq!(
+        snapshot.lookup_exact(&prefix("10.1.0.0/16")).unwrap().data,
+        Some(18)
+    );
+
+    // Changes made in a transaction through a borrow are told on commit.
+    let mut txn = top.transaction();
+    *txn.get_mut(&prefix("10.0.0.0/8")).unwrap() = 5;
+    txn.entry(prefix("10.2.0.0/16"))
+        .unwrap()
+        .and_modify(|n| *n = 0)
+        .or_insert(2);
+    txn.commit();
+    check(&top);
+    assert_eq!(mirror.lock().unwrap().len(), 3);
+}
+
+fn take_sorted(log: &Log) -> Vec<String> {
+    let mut changes = take(log);
+    changes.sort();
+    changes
+}
+
+#[test]
+fn observer_bulk() {
+    let mut top = ArtRoot::<Ipv4Net, u32>::new_ipv4_table();
+    let (log, _) = observe(&mut top);
+
+    top.extend([(prefix("10.0.0.0/8"), 8), (prefix("10.1.0.0/16"), 16)]);
+    top.extend([(prefix("192.168.0.0/16"), 16)]);
+    assert_eq!(take(&log).len(), 3);
+
+    top.retain(|_, data| data != Some(&16));
+    assert_eq!(
+        take_sorted(&log),
+        [
+            "delete 10.1.0.0/16 Some(16)",
+            "delete 192.168.0.0/16 Some(16)"
+        ]
+    );
+
+    // Data modified in place is told once it is no longer borrowed.
+    *top.get_mut(&prefix("10.0.0.0/8")).unwrap() = 9;
+    assert_eq!(take(&log), ["replace 10.0.0.0/8 Some(8) Some(9)"]);
+    assert_eq!(*top.get_mut(&prefix("10.0.0.0/8")).unwrap(), 9);
+    assert!(take(&log).is_empty());
+
+    top.route_ipv4_add(prefix("0.0.0.0/0"), 0);
+    take(&log);
+    top.clear();
+    assert_eq!(
+        take_sorted(&log),
+        ["delete 0.0.0.0/0 Some(0)", "delete 10.0.0.0/8 Some(9)"]
+    );
+}
+
+#[test]
+fn observer_transaction() {
+    let mut top = ArtRoot::<Ipv4Net, u32>::new_ipv4_table();
+    let (log, _) = observe(&mut top);
+    top.route_ipv4_add(prefix("10.0.0.0/8"), 8);
+    take(&log);
+
+    // Nothing is told about a transaction rolled back.
+    let mut txn = top.transaction();
+    txn.route_ipv4_add(prefix("10.1.0.0/16"), 16);
+    txn.rollback();
+    assert!(take(&log).is_empty());
+
+    // The changes of a transaction are told once it commits.
+    let mut txn = top.transaction();
+    txn.route_ipv4_add(prefix("10.1.0.0/16"), 16);
+    txn.route_ipv4_delete(prefix("10.0.0.0/8"));
+    assert!(take(&log).is_empty());
+    txn.commit();
+    assert_eq!(
+        take(&log),
+        ["add 10.1.0.0/16 Some(16)", "delete 10.0.0.0/8 Some(8)"]
+    );
+
+    // Snapshots and the tables made from them have no observers.
+    let mut copy = top.snapshot().to_root();
+    copy.route_ipv4_add(prefix("10.2.0.0/16"), 16);
+    assert!(take(&log).is_empty());
+
+    // The observers stay with the table after a commit.
+    top.route_ipv4_delete(prefix("10.1.0.0/16"));
+    assert_eq!(take(&log), ["delete 10.1.0.0/16 Some(16)"]);
+}
+
+#[test]
+fn observer_rcu() {
+    let top = ArtRcuRoot::<Ipv4Net, u32>::new_ipv4_table();
+    let log = Log::default();
+    let changes = log.clone();
+    let id = top.subscribe(move |change| changes.lock().unwrap().push(describe(change)));
+
+    top.route_add(prefix("10.0.0.0/8"), 8);
+    let mut txn = top.transaction();
+    txn.route_ipv4_add(prefix("10.0.0.0/8"), 9);
+    txn.route_ipv4_add(prefix("10.1.0.0/16"), 16);
+    assert_eq!(take(&log), ["add 10.0.0.0/8 Some(8)"]);
+    txn.commit();
+    assert_eq!(
+        take(&log),
+        [
+            "replace 10.0.0.0/8 Some(8) Some(9)",
+            "add 10.1.0.0/16 Some(16)"
+        ]
+    );
+
+    assert!(top.unsubscribe(id));
+    top.route_delete(prefix("10.0.0.0/8"));
+    assert!(take(&log).is_empty());
+}
@@ -0,0 +1,197 @@
+use art::*;
+use ipnet::Ipv4Net;
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+fn stable(i: u32) -> Ipv4Net {
+    Ipv4Net::new(Ipv4Addr::from(0x0a000000 | (i << 16)), 16).unwrap()
+}
+
+fn flapping(i: u32) -> Ipv4Net {
+    Ipv4Net::new(Ipv4Addr::from(0x0a000000 | (i << 16)), 24).unwrap()
+}
+
+#[test]
+fn rcu_lookup() {
+    let top = ArtRcuRoot::<Ipv4Net, u32>::new_ipv4_table();
+
+    assert!(top.route_add(stable(1), 16).is_none());
+    assert!(top.route_add(flapping(1), 24).is_none());
+    assert_eq!(top.route_add(flapping(1), 25).unwrap().data, Some(24));
+
+    let node = top.lookup_addr(Ipv4Addr::new(10, 1, 0, 1)).unwrap();
+    assert_eq!(node.data, Some(25));
+    let node = top.lookup(&"10.1.1.1/32".parse().unwrap()).unwrap();
+    assert_eq!(node.data, Some(16));
+    assert!(top.lookup_exact(&flapping(1)).is_some());
+
+    assert_eq!(top.route_delete(flapping(1)).unwrap().data, Some(25));
+    assert!(top.route_delete(flapping(1)).is_none());
+    let node = top.lookup_addr(Ipv4Addr::new(10, 1, 0, 1)).unwrap();
+    assert_eq!(node.data, Some(16));
+}
+
+#[test]
+fn rcu_loaded_version() {
+    let top = ArtRcuRoot::<Ipv4Net, u32>::new_ipv4_table();
+    for i in 0..16 {
+        top.route_add(stable(i), i);
+    }
+
+    let version = top.load();
+    for i in 0..16 {
+        top.route_delete(stable(i));
+    }
+
+    // The loaded version still has every route.
+    assert_eq!(version.iter().count(), 16);
+    assert_eq!(version.len(), 16);
+    assert!(version.lookup_exact(&stable(3)).is_some());
+    assert_eq!(top.load().iter().count(), 0);
+    assert!(top.load().is_empty());
+}
+
+#[test]
+fn rcu_reclaim() {
+    let token = Arc::new(());
+    let top = ArtRcuRoot::<Ipv4Net, Arc<()>>::new_ipv4_table();
+    for i in 0..16 {
+        top.route_add(stable(i), token.clone());
+        top.route_add(flapping(i), token.clone());
+    }
+    assert_eq!(Arc::strong_count(&token), 33);
+
+    // Routes removed while a reader holds an old version are freed only
+    // when that version is dropped.
+    let version = top.load();
+    for i in 0..16 {
+        top.route_delete(flapping(i));
+    }
+    assert_eq!(Arc::strong_count(&token), 33);
+    drop(version);
+    assert_eq!(Arc::strong_count(&token), 17);
+
+    drop(top);
+    assert_eq!(Arc::strong_count(&token), 1);
+}
+
+fn root_table(top: &ArtRcuRoot<Ipv4Net, u32>) -> *const ArtTable<Ipv4Net, u32> {
+    Arc::as_ptr(&top.load().root())
+}
+
+#[test]
+fn rcu_spare() {
+    let top =
+        ArtRcuRoot::<Ipv4Net, u32>::new(ArtRoot::with_layout(&ArtLayout::ipv4_16_8_8()).unwrap());
+    top.route_add(stable(1), 16);
+    top.route_add(stable(2), 16);
+    let first = root_table(&top);
+
+    // With no reader holding the replaced version, updates alternate
+    // between two copies of the table rather than copying the root table.
+    top.route_add(flapping(1), 24);
+    let second = root_table(&top);
+    assert_ne!(first, second);
+    top.route_delete(stable(2));
+    assert_eq!(root_table(&top), first);
+    top.route_add(flapping(2), 24);
+    assert_eq!(root_table(&top), second);
+
+    // Changes to the data of a route are made on both copies as well.
+    let mut txn = top.transaction();
+    *txn.get_mut(&stable(1)).unwrap() = 17;
+    *txn.entry(flapping(3)).unwrap().or_insert(0) += 24;
+    txn.commit();
+    let version = top.load();
+    top.route_delete(flapping(1));
+    top.route_add(stable(2), 16);
+
+    // The version held by a reader is left alone.
+    let routes = |root: &ArtRoot<Ipv4Net, u32>| {
+        root.iter_sorted()
+            .map(|node| (node.prefix, node.data.unwrap()))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        routes(&version),
+        [
+            (stable(1), 17),
+            (flapping(1), 24),
+            (flapping(2), 24),
+            (flapping(3), 24)
+        ]
+    );
+    drop(version);
+    for i in 0..4 {
+        top.route_add(stable(3), i);
+        assert_eq!(
+            routes(&top.load()),
+            [
+                (stable(1), 17),
+                (stable(2), 16),
+                (flapping(2), 24),
+                (stable(3), i),
+                (flapping(3), 24)
+            ]
+        );
+    }
+}
+
+#[test]
+fn rcu_stress() {
+    let top = Arc::new(ArtRcuRoot::<Ipv4Net, u32>::new_ipv4_table());
+    for i in 0..256 {
+        top.route_add(stable(i), 16);
+    }
+    let done = Arc::new(AtomicBool::new(false));
+
+    let readers: Vec<_> = (0..4)
+        .map(|n| {
+            let top = top.clone();
+            let done = done.clone();
+            thread::spawn(move || {
+                let mut lookups = 0u64;
+                while !done.load(Ordering::Relaxed) {
+                    for i in 0..256u32 {
+                        let addr = Ipv4Addr::from(0x0a000001 | (i << 16) | (n << 8));
+                        let node = top.lookup_addr(addr).unwrap();
+                        assert!(node.prefix.contains(&addr));
+                        assert_eq!(node.data, Some(node.prefix.prefix_len() as u32));
+                        lookups += 1;
+                    }
+
+                    // A loaded version never changes under the reader.
+                    let version = top.load();
+                    let count = version.iter().count();
+                    assert!((256..=512).contains(&count));
+                    assert_eq!(version.iter().count(), count);
+                }
+                lookups
+            })
+        })
+        .collect();
+
+    let writer = {
+        let top = top.clone();
+        thread::spawn(move || {
+            for _ in 0..20 {
+                for i in 0..256 {
+                    top.route_add(flapping(i), 24);
+                }
+                for i in 0..256 {
+                    assert!(top.route_delete(flapping(i)).is_some());
+                }
+            }
+        })
+    };
+
+    writer.join().unwrap();
+    done.store(true, Ordering::Relaxed);
+    for reader in readers {
+        assert!(reader.join().unwrap() > 0);
+    }
+
+    assert_eq!(top.load().iter().count(), 256);
+}
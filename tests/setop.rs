@@ -0,0 +1,132 @@
+use art::*;
+use ipnet::Ipv4Net;
+use std::sync::{Arc, Mutex};
+
+fn routes(top: &ArtRoot<Ipv4Net, u32>) -> Vec<(String, u32)> {
+    top.iter_sorted()
+        .map(|node| (node.prefix.to_string(), node.data.unwrap()))
+        .collect()
+}
+
+fn table(layout: &ArtLayout, routes: &[(&str, u32)]) -> ArtRoot<Ipv4Net, u32> {
+    let mut top = ArtRoot::with_layout(layout).unwrap();
+    for (prefix, data) in routes {
+        top.route_ipv4_add(prefix.parse().unwrap(), *data);
+    }
+    top
+}
+
+fn expect(routes: &[(&str, u32)]) -> Vec<(String, u32)> {
+    routes.iter().map(|(p, d)| (p.to_string(), *d)).collect()
+}
+
+fn sum(_: &Ipv4Net, a: Option<&u32>, b: Option<&u32>) -> Option<u32> {
+    Some(a.unwrap() + b.unwrap())
+}
+
+const A: &[(&str, u32)] = &[
+    ("10.0.0.0/8", 1),
+    ("10.1.0.0/16", 2),
+    ("10.1.1.0/24", 3),
+    ("172.16.0.0/12", 4),
+    ("192.168.1.0/24", 5),
+];
+
+const B: &[(&str, u32)] = &[
+    ("10.1.0.0/16", 20),
+    ("10.1.1.0/24", 30),
+    ("172.16.1.0/24", 40),
+    ("192.168.0.0/16", 50),
+];
+
+#[test]
+fn setop_exact() {
+    for layout in [ArtLayout::ipv4_8_4_4_4_4_4_4(), ArtLayout::ipv4_16_8_8()] {
+        let b = table(&layout, B);
+
+        let mut top = table(&ArtLayout::ipv4_8_4_4_4_4_4_4(), A);
+        top.union_with(&b, sum);
+        assert_eq!(
+            routes(&top),
+            expect(&[
+                ("10.0.0.0/8", 1),
+                ("10.1.0.0/16", 22),
+                ("10.1.1.0/24", 33),
+                ("172.16.0.0/12", 4),
+                ("172.16.1.0/24", 40),
+                ("192.168.0.0/16", 50),
+                ("192.168.1.0/24", 5),
+            ])
+        );
+        assert_eq!(top.len(), 7);
+
+        let mut top = table(&ArtLayout::ipv4_8_4_4_4_4_4_4(), A);
+        top.intersection(&b, sum);
+        assert_eq!(
+            routes(&top),
+            expect(&[("10.1.0.0/16", 22), ("10.1.1.0/24", 33)])
+        );
+        assert_eq!(top.len(), 2);
+
+        let mut top = table(&ArtLayout::ipv4_8_4_4_4_4_4_4(), A);
+        top.difference(&b);
+        assert_eq!(
+            routes(&top),
+            expect(&[
+                ("10.0.0.0/8", 1),
+                ("172.16.0.0/12", 4),
+                ("192.168.1.0/24", 5)
+            ])
+        );
+        assert_eq!(top.len(), 3);
+        assert_eq!(routes(&b), expect(B));
+    }
+}
+
+#[test]
+fn setop_covered() {
+    let b = table(&ArtLayout::ipv4_8_8_8_8(), B);
+
+    let mut top = table(&ArtLayout::ipv4_8_4_4_4_4_4_4(), A);
+    top.intersection_covered(&b);
+    assert_eq!(
+        routes(&top),
+        expect(&[
+            ("10.1.0.0/16", 2),
+            ("10.1.1.0/24", 3),
+            ("192.168.1.0/24", 5)
+        ])
+    );
+
+    let mut top = table(&ArtLayout::ipv4_8_4_4_4_4_4_4(), A);
+    top.difference_covered(&b);
+    assert_eq!(
+        routes(&top),
+        expect(&[("10.0.0.0/8", 1), ("172.16.0.0/12", 4)])
+    );
+
+    // A default route covers everything.
+    let mut top = table(&ArtLayout::ipv4_8_4_4_4_4_4_4(), A);
+    top.difference_covered(&table(&ArtLayout::ipv4_8_8_8_8(), &[("0.0.0.0/0", 0)]));
+    assert!(top.is_empty());
+}
+
+#[test]
+fn setop_observer() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let changes = log.clone();
+    let mut top = table(&ArtLayout::ipv4_8_4_4_4_4_4_4(), A);
+    top.subscribe(move |change: &ArtChange<Ipv4Net, u32>| {
+        changes.lock().unwrap().push(change.prefix().to_string())
+    });
+
+    // Routes merged to the same data are not changed.
+    top.union_with(&table(&ArtLayout::ipv4_8_8_8_8(), B), |_, a, _| a.copied());
+    assert_eq!(*log.lock().unwrap(), ["172.16.1.0/24", "192.168.0.0/16"]);
+    assert_eq!(
+        top.lookup_exact(&"10.1.0.0/16".parse().unwrap())
+            .unwrap()
+            .data,
+        Some(2)
+    );
+}
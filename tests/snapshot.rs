@@ -0,0 +1,105 @@
+use art::*;
+use ipnet::Ipv4Net;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::thread;
+
+fn routes(top: &ArtRoot<Ipv4Net, u32>) -> Vec<(String, u32)> {
+    top.iter_sorted()
+        .map(|node| (node.prefix.to_string(), node.data.unwrap()))
+        .collect()
+}
+
+fn sub_table(top: &ArtRoot<Ipv4Net, u32>, i: u64) -> Arc<ArtTable<Ipv4Net, u32>> {
+    match top.root().get_entry(i) {
+        ArtEntry::Table(table) => table.clone(),
+        _ => panic!("no sub-table at {}", i),
+    }
+}
+
+#[test]
+fn snapshot_isolation() {
+    let mut top = ArtRoot::<Ipv4Net, u32>::new_ipv4_table();
+    top.route_ipv4_add("10.0.0.0/8".parse().unwrap(), 8);
+    top.route_ipv4_add("10.1.0.0/16".parse().unwrap(), 16);
+    top.route_ipv4_add("192.168.1.0/24".parse().unwrap(), 24);
+
+    let snapshot = top.snapshot();
+    let expected = routes(&snapshot);
+    assert_eq!(snapshot.len(), 3);
+
+    top.route_ipv4_add("10.1.1.0/24".parse().unwrap(), 24);
+    top.route_ipv4_delete("10.0.0.0/8".parse().unwrap());
+    *top.get_mut(&"10.1.0.0/16".parse().unwrap()).unwrap() = 17;
+    top.retain(|prefix, _| prefix.prefix_len() < 24);
+
+    assert_eq!(routes(&snapshot), expected);
+    assert_eq!(snapshot.len(), 3);
+    let node = snapshot.lookup_addr(Ipv4Addr::new(10, 1, 1, 1)).unwrap();
+    assert_eq!(node.data, Some(16));
+    let node = snapshot.lookup_addr(Ipv4Addr::new(10, 2, 0, 1)).unwrap();
+    assert_eq!(node.data, Some(8));
+    assert_eq!(routes(&top), [("10.1.0.0/16".to_string(), 17)]);
+
+    top.clear();
+    assert_eq!(snapshot.iter().count(), 3);
+    assert_eq!((&snapshot).into_iter().count(), 3);
+}
+
+#[test]
+fn snapshot_sharing() {
+    let mut top = ArtRoot::<Ipv4Net, u32>::new_ipv4_table();
+    top.route_ipv4_add("10.1.0.0/16".parse().unwrap(), 16);
+    top.route_ipv4_add("192.168.1.0/24".parse().unwrap(), 24);
+
+    // Nothing is copied until the live table is modified.
+    let snapshot = top.snapshot();
+    assert!(Arc::ptr_eq(&top.root(), &snapshot.root()));
+
+    // Only the path of the update is copied.
+    top.route_ipv4_add("10.1.1.0/24".parse().unwrap(), 24);
+    assert!(!Arc::ptr_eq(&top.root(), &snapshot.root()));
+    assert!(!Arc::ptr_eq(
+        &sub_table(&top, 256 + 10),
+        &sub_table(&snapshot, 256 + 10)
+    ));
+    assert!(Arc::ptr_eq(
+        &sub_table(&top, 256 + 192),
+        &sub_table(&snapshot, 256 + 192)
+    ));
+
+    // A table created from the snapshot is independent of both.
+    let mut copy = snapshot.to_root();
+    copy.route_ipv4_delete("192.168.1.0/24".parse().unwrap());
+    assert_eq!(copy.len(), 1);
+    assert_eq!(snapshot.len(), 2);
+    assert_eq!(top.len(), 3);
+    assert_eq!(snapshot.clone().len(), 2);
+}
+
+#[test]
+fn snapshot_dump_while_updating() {
+    let mut top = ArtRoot::<Ipv4Net, u32>::new_ipv4_table();
+    for i in 0..256u32 {
+        let prefix = Ipv4Net::new(Ipv4Addr::from(0x0a000000 | (i << 8)), 24).unwrap();
+        top.route_ipv4_add(prefix, 0);
+    }
+
+    // Every snapshot sees all of a generation of the routes, and none of
+    // the next one.
+    let mut dumpers = Vec::new();
+    for generation in 1..8u32 {
+        let snapshot = top.snapshot();
+        dumpers.push(thread::spawn(move || {
+            let data: Vec<u32> = snapshot.iter().map(|node| node.data.unwrap()).collect();
+            assert_eq!(data.len(), 256);
+            assert!(data.iter().all(|&data| data == generation - 1));
+        }));
+        for (_, mut data) in top.iter_mut() {
+            *data = generation;
+        }
+    }
+    for dumper in dumpers {
+        dumper.join().unwrap();
+    }
+}
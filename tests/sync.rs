@@ -0,0 +1,83 @@
+use art::*;
+use ipnet::{Ipv4Net, Ipv6Net};
+use std::net::Ipv4Addr;
+use std::sync::{Arc, RwLock};
+use std::thread;
+
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn sync_send_sync() {
+    assert_send_sync::<ArtRoot<Ipv4Net, u32>>();
+    assert_send_sync::<ArtRoot<Ipv6Net, String>>();
+    assert_send_sync::<ArtDualRoot<u32>>();
+    assert_send_sync::<ArtNode<Ipv4Net, u32>>();
+    assert_send_sync::<ArtArenaRoot<Ipv6Net, String>>();
+    assert_send_sync::<ArtSnapshot<Ipv4Net, u32>>();
+}
+
+#[test]
+fn sync_move_to_thread() {
+    let mut top = ArtRoot::<Ipv4Net, u32>::new_ipv4_table();
+    top.route_ipv4_add("10.0.0.0/8".parse().unwrap(), 8);
+
+    let top = thread::spawn(move || {
+        top.route_ipv4_add("10.0.0.0/24".parse().unwrap(), 24);
+        top
+    })
+    .join()
+    .unwrap();
+
+    assert_eq!(top.iter().count(), 2);
+    let node = top.lookup_addr(Ipv4Addr::new(10, 0, 0, 1)).unwrap();
+    assert_eq!(node.data, Some(24));
+}
+
+#[test]
+fn sync_reader_writer() {
+    let mut top = ArtRoot::<Ipv4Net, u32>::new_ipv4_table();
+    for i in 0..64u32 {
+        let prefix = Ipv4Net::new(Ipv4Addr::from(0x0a000000 | (i << 16)), 16).unwrap();
+        top.route_ipv4_add(prefix, i);
+    }
+    let top = Arc::new(RwLock::new(top));
+
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let top = top.clone();
+            thread::spawn(move || {
+                for _ in 0..200 {
+                    for i in 0..64u32 {
+                        let addr = Ipv4Addr::from(0x0a000001 | (i << 16));
+                        let node = top.read().unwrap().lookup_addr(addr).unwrap();
+                        assert!(node.prefix.contains(&addr));
+                    }
+                }
+            })
+        })
+        .collect();
+
+    // More specific routes come and go while the readers are running.
+    let writer = {
+        let top = top.clone();
+        thread::spawn(move || {
+            for _ in 0..200 {
+                for i in 0..64u32 {
+                    let prefix = Ipv4Net::new(Ipv4Addr::from(0x0a000000 | (i << 16)), 24).unwrap();
+                    top.write().unwrap().route_ipv4_add(prefix, i);
+                }
+                for i in 0..64u32 {
+                    let prefix = Ipv4Net::new(Ipv4Addr::from(0x0a000000 | (i << 16)), 24).unwrap();
+                    top.write().unwrap().route_ipv4_delete(prefix);
+                }
+            }
+        })
+    };
+
+    for reader in readers {
+        reader.join().unwrap();
+    }
+    writer.join().unwrap();
+
+    assert_eq!(top.read().unwrap().iter().count(), 64);
+}
@@ -0,0 +1,112 @@
+use art::*;
+use ipnet::Ipv4Net;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+fn table() -> ArtRoot<Ipv4Net, u32> {
+    let mut top = ArtRoot::<Ipv4Net, u32>::new_ipv4_table();
+    top.route_ipv4_add("10.0.0.0/8".parse().unwrap(), 8);
+    top.route_ipv4_add("10.1.0.0/16".parse().unwrap(), 16);
+    top.route_ipv4_add("192.168.1.0/24".parse().unwrap(), 24);
+    top
+}
+
+fn update(txn: &mut ArtRoot<Ipv4Net, u32>) {
+    txn.route_ipv4_add("10.1.1.0/24".parse().unwrap(), 24);
+    txn.route_ipv4_add("10.1.0.0/16".parse().unwrap(), 17);
+    txn.route_ipv4_delete("192.168.1.0/24".parse().unwrap());
+}
+
+fn data(top: &ArtRoot<Ipv4Net, u32>, addr: Ipv4Addr) -> Option<u32> {
+    top.lookup_addr(addr).and_then(|node| node.data)
+}
+
+#[test]
+fn transaction_commit() {
+    let mut top = table();
+    let before = top.snapshot();
+
+    let mut txn = top.transaction();
+    update(&mut txn);
+
+    // The transaction sees its own updates.
+    assert_eq!(txn.len(), 3);
+    assert_eq!(data(&txn, Ipv4Addr::new(10, 1, 1, 1)), Some(24));
+    assert_eq!(data(&txn, Ipv4Addr::new(10, 1, 2, 1)), Some(17));
+    assert_eq!(data(&txn, Ipv4Addr::new(192, 168, 1, 1)), None);
+    txn.commit();
+
+    assert_eq!(top.len(), 3);
+    assert_eq!(data(&top, Ipv4Addr::new(10, 1, 1, 1)), Some(24));
+    assert_eq!(data(&top, Ipv4Addr::new(10, 1, 2, 1)), Some(17));
+    assert_eq!(data(&top, Ipv4Addr::new(192, 168, 1, 1)), None);
+
+    // A snapshot taken before the commit has none of the updates.
+    assert_eq!(before.len(), 3);
+    assert_eq!(data(&before, Ipv4Addr::new(10, 1, 1, 1)), Some(16));
+    assert_eq!(data(&before, Ipv4Addr::new(192, 168, 1, 1)), Some(24));
+}
+
+#[test]
+fn transaction_rollback() {
+    let mut top = table();
+    let root = top.root();
+
+    let mut txn = top.transaction();
+    update(&mut txn);
+    txn.retain(|_, _| false);
+    assert!(txn.is_empty());
+    txn.rollback();
+
+    // The table has not even been copied.
+    assert!(Arc::ptr_eq(&top.root(), &root));
+    assert_eq!(top.len(), 3);
+    assert_eq!(data(&top, Ipv4Addr::new(10, 1, 1, 1)), Some(16));
+
+    // Dropping the transaction rolls it back as well.
+    {
+        let mut txn = top.transaction();
+        update(&mut txn);
+    }
+    assert!(Arc::ptr_eq(&top.root(), &root));
+    assert_eq!(data(&top, Ipv4Addr::new(192, 168, 1, 1)), Some(24));
+}
+
+#[test]
+fn transaction_rcu() {
+    let top = Arc::new(ArtRcuRoot::<Ipv4Net, u32>::new(table()));
+
+    // Readers see the whole batch at once or nothing of it.
+    let reader = {
+        let top = top.clone();
+        thread::spawn(move || loop {
+            let version = top.load();
+            let old = data(&version, Ipv4Addr::new(10, 1, 1, 1)) == Some(16);
+            assert_eq!(
+                version
+                    .lookup_exact(&"192.168.1.0/24".parse().unwrap())
+                    .is_some(),
+                old
+            );
+            assert_eq!(version.len(), 3);
+            if !old {
+                break;
+            }
+        })
+    };
+
+    let mut txn = top.transaction();
+    update(&mut txn);
+    thread::sleep(Duration::from_millis(10));
+    assert_eq!(data(&top.load(), Ipv4Addr::new(10, 1, 1, 1)), Some(16));
+    txn.commit();
+    reader.join().unwrap();
+    assert_eq!(data(&top.load(), Ipv4Addr::new(10, 1, 1, 1)), Some(24));
+
+    let mut txn = top.transaction();
+    txn.clear();
+    txn.rollback();
+    assert_eq!(top.load().len(), 3);
+}